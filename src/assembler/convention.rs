@@ -0,0 +1,265 @@
+//! Calling convention of the integer registers.
+//!
+//! | registers   | role                                      |
+//! |-------------|-------------------------------------------|
+//! | `$0`        | return value                              |
+//! | `$1`–`$7`   | arguments, in order                       |
+//! | `$8`–`$19`  | temporaries, may be clobbered by a call   |
+//! | `$20`–`$31` | callee-saved, preserved across a call     |
+//!
+//! Float registers are all caller-saved. Host functions called with
+//! `SYSCALL` follow the same convention.
+//!
+//! `.func name(a, b)` ... `.endfunc` declares a function: `name` becomes a
+//! label, `$a` and `$b` name `$1` and `$2` inside the body, and the
+//! callee-saved registers the body mentions are pushed on entry and
+//! reloaded from the frame before every `ret`. The saved registers take
+//! the first words below `bp`, so locals pushed afterwards start below
+//! them.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::{
+    assembler::SymbolType,
+    instruction::Instruction,
+    program::Program,
+    verify::successors,
+};
+
+pub const RETURN_REGISTER: usize = 0;
+pub const ARGUMENT_REGISTERS: std::ops::RangeInclusive<usize> = 1..=7;
+pub const CALLEE_SAVED: std::ops::RangeInclusive<usize> = 20..=31;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Violation {
+    /// Written before it was pushed.
+    Unsaved,
+    /// Still clobbered when the function returns.
+    NotRestored,
+}
+
+/// A function that does not preserve a callee-saved register.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConventionWarning {
+    pub function: String,
+    pub pc: usize,
+    pub register: usize,
+    pub violation: Violation,
+}
+
+impl fmt::Display for ConventionWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.violation {
+            Violation::Unsaved => write!(
+                f,
+                "function `{}` clobbers callee-saved register ${} at pc {} without saving it",
+                self.function, self.register, self.pc,
+            ),
+            Violation::NotRestored => write!(
+                f,
+                "function `{}` returns at pc {} without restoring callee-saved register ${}",
+                self.function, self.pc, self.register,
+            ),
+        }
+    }
+}
+
+/// Callee-saved registers pushed on every path so far, and those written
+/// since without being restored on some path.
+#[derive(Debug, PartialEq, Clone, Default)]
+struct State {
+    saved: BTreeSet<usize>,
+    dirty: BTreeSet<usize>,
+}
+
+impl State {
+    fn merge(&self, other: &State) -> State {
+        State {
+            saved: self.saved.intersection(&other.saved).copied().collect(),
+            dirty: self.dirty.union(&other.dirty).copied().collect(),
+        }
+    }
+
+    /// `POP` or frame load into a saved register.
+    fn restores(&self, instruction: &Instruction) -> bool {
+        match *instruction {
+            Instruction::POP { rd } | Instruction::LOADBP { rd, .. } => self.saved.contains(&rd),
+            _ => false,
+        }
+    }
+
+    fn step(&self, instruction: &Instruction) -> State {
+        let mut state = self.clone();
+
+        match *instruction {
+            Instruction::PUSH { rs } => {
+                state.saved.insert(rs);
+            }
+            Instruction::POP { rd } | Instruction::LOADBP { rd, .. } if self.restores(instruction) => {
+                state.dirty.remove(&rd);
+            }
+            _ => state.dirty.extend(instruction.writes().into_iter().filter(|r| CALLEE_SAVED.contains(r))),
+        }
+
+        state
+    }
+}
+
+/// Checks every `CALL` target for callee-saved registers that are not
+/// preserved: written before being pushed, or not reloaded by a `POP` or
+/// frame load after the last write on some path to a `RET`. The check
+/// follows jumps but not calls, so each function is judged on its own
+/// body.
+pub fn check_convention(program: &Program) -> Vec<ConventionWarning> {
+    let entries = program.instructions.iter()
+        .filter_map(|instruction| match *instruction {
+            Instruction::CALL { dst } => Some(dst),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+
+    let mut warnings = Vec::new();
+    for entry in entries {
+        let mut reported = BTreeSet::new();
+
+        for (pc, state) in states(program, entry) {
+            let instruction = &program.instructions[pc];
+
+            let found = if *instruction == Instruction::RET {
+                state.dirty.iter().map(|r| (Violation::NotRestored, *r)).collect()
+            } else if state.restores(instruction) {
+                vec![]
+            } else {
+                instruction.writes().into_iter()
+                    .filter(|r| CALLEE_SAVED.contains(r) && !state.saved.contains(r))
+                    .map(|r| (Violation::Unsaved, r))
+                    .collect::<Vec<_>>()
+            };
+
+            for (violation, register) in found {
+                if reported.insert((violation, register)) {
+                    warnings.push(ConventionWarning { function: function_name(program, entry), pc, register, violation });
+                }
+            }
+        }
+    }
+
+    warnings
+}
+
+/// State before each instruction reachable from `entry` without following
+/// calls, computed until it no longer changes.
+fn states(program: &Program, entry: usize) -> BTreeMap<usize, State> {
+    let mut states = BTreeMap::new();
+    let mut pending = Vec::new();
+
+    if entry < program.instructions.len() {
+        states.insert(entry, State::default());
+        pending.push(entry);
+    }
+
+    while let Some(pc) = pending.pop() {
+        let instruction = &program.instructions[pc];
+        let out = states[&pc].step(instruction);
+
+        for next in successors(program, instruction, pc, false) {
+            if next >= program.instructions.len() {
+                continue;
+            }
+
+            let merged = states.get(&next).map_or_else(|| out.clone(), |state: &State| state.merge(&out));
+            if states.get(&next) != Some(&merged) {
+                states.insert(next, merged);
+                pending.push(next);
+            }
+        }
+    }
+
+    states
+}
+
+fn function_name(program: &Program, entry: usize) -> String {
+    program.symbols.iter()
+        .find(|(_, stype)| **stype == SymbolType::Label(entry))
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| format!("L{}", entry))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::Parser;
+
+    #[test]
+    fn clobbers() {
+        let program = Parser::new().process("
+.data
+.code
+call @careless
+call @careful
+hlt
+careless:
+load $20 #1
+load $21 #2
+load $20 #3
+load $8 #4
+ret
+careful:
+push $22
+load $22 #1
+pop $22
+ret
+").unwrap();
+
+        let warning = |function: &str, pc, register, violation| {
+            ConventionWarning { function: function.into(), pc, register, violation }
+        };
+
+        assert_eq!(check_convention(&program), vec![
+            warning("careless", 3, 20, Violation::Unsaved),
+            warning("careless", 4, 21, Violation::Unsaved),
+            warning("careless", 7, 20, Violation::NotRestored),
+            warning("careless", 7, 21, Violation::NotRestored),
+        ]);
+    }
+
+    #[test]
+    fn restores_on_every_path() {
+        let program = Parser::new().process("
+.data
+.code
+call @forgetful
+call @branchy
+call @framed
+hlt
+forgetful:
+push $20
+load $20 #1
+ret
+branchy:
+push $21
+load $21 #1
+jmpe @early
+pop $21
+ret
+early:
+ret
+.func framed()
+load $22 #1
+jmpe @out
+ret
+out:
+ret
+.endfunc
+").unwrap();
+
+        assert_eq!(check_convention(&program), vec![
+            ConventionWarning { function: "forgetful".into(), pc: 6, register: 20, violation: Violation::NotRestored },
+            ConventionWarning { function: "branchy".into(), pc: 12, register: 21, violation: Violation::NotRestored },
+        ]);
+    }
+}
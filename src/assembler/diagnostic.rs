@@ -0,0 +1,131 @@
+use std::fmt;
+
+/// An assembler error located in the source text.
+///
+/// `line` and `column` are 1-based, `len` is the number of characters to
+/// underline in `snippet`, the source line the error starts on. `notes`
+/// point at related places, such as the macro invocation an error comes
+/// from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+    pub snippet: String,
+    pub message: String,
+    pub notes: Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+    /// Locates the byte range `start..end` of `source`. Ranges spanning
+    /// several lines are underlined up to the end of the first one. Offsets
+    /// inside a multibyte character are widened to the whole character.
+    pub fn new(source: &str, start: usize, end: usize, message: String) -> Self {
+        let mut start = start.min(source.len());
+        while !source.is_char_boundary(start) {
+            start -= 1;
+        }
+
+        let mut end = end.max(start).min(source.len());
+        while !source.is_char_boundary(end) {
+            end += 1;
+        }
+
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+        let snippet = source[line_start..line_end].trim_end_matches('\r');
+
+        let line = source[..start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let len = source[start..end.min(line_end)].trim_end().chars().count().max(1);
+
+        Self {
+            line,
+            column,
+            len,
+            snippet: snippet.to_string(),
+            message,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_note(mut self, source: &str, start: usize, end: usize, message: String) -> Self {
+        self.notes.push(Diagnostic::new(source, start, end, message));
+
+        self
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, level: &str) -> fmt::Result {
+        let gutter = self.line.to_string().len();
+
+        writeln!(f, "{}: {}", level, self.message)?;
+        writeln!(f, "{:w$}--> {}:{}", "", self.line, self.column, w = gutter)?;
+        writeln!(f, "{:w$} |", "", w = gutter)?;
+        writeln!(f, "{} | {}", self.line, self.snippet)?;
+        write!(f, "{:w$} | {:c$}{}", "", "", "^".repeat(self.len), w = gutter, c = self.column - 1)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, "error")?;
+
+        for note in &self.notes {
+            writeln!(f)?;
+            note.write(f, "note")?;
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locate() {
+        let source = ".data\n.code\n  jmp @nowhere\nhlt\n";
+        let start = source.find("@nowhere").unwrap();
+        let diagnostic = Diagnostic::new(source, start, start + 8, "unknown label".to_string());
+
+        assert_eq!(3, diagnostic.line);
+        assert_eq!(7, diagnostic.column);
+        assert_eq!("  jmp @nowhere", diagnostic.snippet);
+        assert_eq!(
+            "error: unknown label\n --> 3:7\n  |\n3 |   jmp @nowhere\n  |       ^^^^^^^^",
+            diagnostic.to_string(),
+        );
+    }
+
+    #[test]
+    fn notes() {
+        let source = "hlt\n  ret\n";
+        let diagnostic = Diagnostic::new(source, 0, 3, "first".to_string())
+            .with_note(source, 6, 9, "second".to_string());
+
+        assert_eq!(
+            "error: first\n --> 1:1\n  |\n1 | hlt\n  | ^^^\nnote: second\n --> 2:3\n  |\n2 |   ret\n  |   ^^^",
+            diagnostic.to_string(),
+        );
+    }
+
+    #[test]
+    fn multibyte() {
+        let source = "load $0 é\nhlt";
+        let diagnostic = Diagnostic::new(source, 8, 9, "syntax error".to_string());
+
+        assert_eq!((1, 9, 1), (diagnostic.line, diagnostic.column, diagnostic.len));
+
+        let diagnostic = Diagnostic::new(source, 9, 9, "syntax error".to_string());
+        assert_eq!((1, 9, 1), (diagnostic.line, diagnostic.column, diagnostic.len));
+    }
+
+    #[test]
+    fn end_of_input() {
+        let diagnostic = Diagnostic::new("hlt", 3, 3, "unexpected end".to_string());
+
+        assert_eq!((1, 4, 1), (diagnostic.line, diagnostic.column, diagnostic.len));
+    }
+}
@@ -0,0 +1,20 @@
+#! stupid_vm
+; Iterative factorial of 6, the result is the exit status.
+
+.data
+
+    ; nothing yet
+
+
+.code
+        load $0 #1          ; accumulator
+        load $1 #6          ; counter
+        load $2 #1
+
+again:
+        mul $0 $0 $1        ; acc *= n
+        sub $1 $1 $2
+        gte $1 $2
+        jmpe @again
+
+        hlt                 ; exit with $0
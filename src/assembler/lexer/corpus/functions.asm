@@ -0,0 +1,21 @@
+; Calling convention example
+
+.data
+.code
+    load $1 #3
+    load $2 #4
+    call @max
+    hlt
+
+.func max(a, b)
+    gte $a $b           ;; a >= b ?
+    jmpe @first
+    load $0 #0
+    add $0 $0 $b
+    ret
+first:
+    load $0 #0
+    add $0 $0 $a
+    ret
+.endfunc
+; end of file, no trailing newline
\ No newline at end of file
@@ -0,0 +1,17 @@
+.data
+	; strings and numbers, tab indented
+	greeting: .asciiz "Hello, world! ; not a comment\n"
+
+	answer: .integer #42	; trailing comment
+
+	.jumptable handlers: @first, @second
+.code
+	load $1 @answer	
+	loadw $1 [$1]
+	load $2 #0
+	switch $2 @handlers @first
+
+first:	hlt
+second:
+	; unreachable
+	igl
@@ -1,4 +1,4 @@
-use std::fmt::Debug;
+use std::{convert::TryFrom, fmt::Debug};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Node<T> {
@@ -11,70 +11,240 @@ pub type TokenNode = Node<Token>;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
-    Int(i32),
+    Int(i64),
     Float(f32),
     Ident(String),
-    String(String),
+    String(Vec<u8>),
     Register(usize),
+    FloatRegister(usize),
+    RegisterName(String),
+    AddressOf(String),
+    Address { base: usize, offset: i32 },
+    FrameAddress { offset: i32 },
+    Expr(Node<ConstExpr>),
+    MacroParam(String),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+}
+
+/// Constant expression, evaluated when the program is assembled.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConstExpr {
+    Int(i64),
+    Name(String),
+    AddressOf(String),
+    MacroParam(String),
+    Neg(Box<Node<ConstExpr>>),
+    Not(Box<Node<ConstExpr>>),
+    Binary(BinOp, Box<Node<ConstExpr>>, Box<Node<ConstExpr>>),
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Declare {
     ConstI64(TokenNode, TokenNode),
     ConstString(TokenNode, TokenNode),
+    ConstAscii(TokenNode, TokenNode),
+    ConstBytes(TokenNode, Vec<TokenNode>),
+    JumpTable(TokenNode, Vec<TokenNode>),
+    Equ(Node<String>, Node<ConstExpr>),
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
     Call(String, Vec<TokenNode>),
-    Label(String, Box<Expression>),
+    Label(Node<String>, Box<Expression>),
+    Func(Node<String>, Vec<Node<String>>),
+    EndFunc,
+    Macro(Node<String>, Vec<Node<String>>),
+    EndMacro,
+}
+
+fn binary(op: BinOp, x: Node<ConstExpr>, y: Node<ConstExpr>) -> ConstExpr {
+    ConstExpr::Binary(op, Box::new(x), Box::new(y))
 }
 
 
 peg::parser! {
     pub grammar assembler() for str {
         rule _()                =   quiet!{[' ' | '\t']}+
-        rule __()               =   quiet!{[' ' | '\t' | '\n']}+
+        rule __()               =   quiet!{(_ / comment() / newline())+}
+        rule newline()          =   "\r\n" / "\n"
+
+        // `;` and `#!` start a comment running to the end of the line.
+        rule comment()          =   (";" / "#!") (!newline() [_])*
 
         rule at()               = ['@']
+        rule amp()              = ['&']
         rule dot()              = ['.']
         rule sharp()            = ['#']
         rule colon()            = [':']
         rule dollar()           = ['$']
         rule dec()              = ['0'..='9']
+        rule hex()              = ['0'..='9' | 'a'..='f' | 'A'..='F']
+        rule oct()              = ['0'..='7']
+        rule bin()              = ['0' | '1']
         rule sign()             = ['+' | '-']
         rule alpha()            = ['a'..='z' | 'A'..='Z']
         rule alphanum()         = ['a'..='z' | 'A'..='Z' | '0'..='9']
-        rule printable()        = ['a'..='z' | 'A'..='Z' | '0'..='9' | ' ']
 
-        pub rule int() -> i32
-        = raw:$(sign()? dec()+)
-        { raw.parse().unwrap() }
+        // Digits may be grouped with `_`, as in Rust. Literals too large for
+        // `i64` are syntax errors, the parser checks the instruction's range.
+        rule magnitude() -> u64
+        = "0x" raw:$(hex() (hex() / "_")*)
+        {? u64::from_str_radix(&raw.replace('_', ""), 16).or(Err("integer literal")) }
+        / "0o" raw:$(oct() (oct() / "_")*)
+        {? u64::from_str_radix(&raw.replace('_', ""), 8).or(Err("integer literal")) }
+        / "0b" raw:$(bin() (bin() / "_")*)
+        {? u64::from_str_radix(&raw.replace('_', ""), 2).or(Err("integer literal")) }
+        / raw:$(dec() (dec() / "_")*)
+        {? raw.replace('_', "").parse().or(Err("integer literal")) }
+        / "'" b:byte_escape() "'"
+        { b as u64 }
+        / "'" c:single_quoted() "'"
+        { c as u64 }
+
+        pub rule int() -> i64
+        = sign:$(sign()?) m:magnitude()
+        {? i64::try_from(if sign == "-" { -(m as i128) } else { m as i128 }).or(Err("integer literal")) }
+
+        rule offset() -> i32
+        = i:int()
+        {? i32::try_from(i).or(Err("offset in i32 range")) }
 
         pub rule uint() -> u32
         = raw:$(dec()+)
-        { raw.parse().unwrap() }
+        {? raw.parse().or(Err("register number")) }
 
         pub rule float() -> f32
-        = raw:$(sign()? dec()+ "."? (dec()+)?)
+        = raw:$(sign()? dec()+ "." dec()*)
         { raw.parse().unwrap() }
 
         pub rule ident() -> String
         = s:$(alphanum()+)
         { s.to_string() }
 
-        pub rule string() -> String
-        = "'" s:$(printable()+) "'"
-        { s.to_string() }
+        rule escape() -> char
+        = "\\n" { '\n' }
+        / "\\t" { '\t' }
+        / "\\r" { '\r' }
+        / "\\0" { '\0' }
+        / "\\\\" { '\\' }
+        / "\\\"" { '"' }
+        / "\\'" { '\'' }
+        / "\\u{" raw:$(hex()*<1,6>) "}"
+        {? u32::from_str_radix(raw, 16).ok().and_then(std::char::from_u32).ok_or("unicode scalar value") }
+
+        // `\xNN` is a single raw byte, unlike in Rust it may be above `\x7F`
+        // and so make a string that is not UTF-8.
+        rule byte_escape() -> u8
+        = "\\x" raw:$(hex() hex())
+        { u8::from_str_radix(raw, 16).unwrap() }
+
+        rule single_quoted() -> char
+        = escape() / !['\'' | '\\' | '\n' | '\r'] c:$([_]) { c.chars().next().unwrap() }
+
+        rule double_quoted() -> char
+        = escape() / !['"' | '\\' | '\n' | '\r'] c:$([_]) { c.chars().next().unwrap() }
+
+        pub rule string() -> Vec<u8>
+        = "'" s:(b:byte_escape() { vec![b] } / c:single_quoted() { c.to_string().into_bytes() })* "'"
+        { s.concat() }
+        / "\"" s:(b:byte_escape() { vec![b] } / c:double_quoted() { c.to_string().into_bytes() })* "\""
+        { s.concat() }
+
+        pub rule address() -> (usize, i32)
+        = "[" dollar() base:uint() offset:offset()? "]"
+        { (base as usize, offset.unwrap_or(0)) }
+
+        pub rule frame_address() -> i32
+        = "[bp" offset:offset()? "]"
+        { offset.unwrap_or(0) }
 
         rule label_declare() -> String
         = s:ident() colon()
         { s }
 
+        // Code label, inside a macro `\@` is replaced by `__m<N>`, unique to
+        // each expansion. Plain names cannot contain `_`, so the suffix is
+        // reserved and only accepted to read back disassembled code.
+        rule symbol() -> String
+        = s:$(alphanum()+ ("\\@" / "__m" dec()+)?)
+        { s.to_string() }
+
+        rule label_node() -> Node<String>
+        = start:position!() expr:symbol() end:position!() colon()
+        { Node { start, end, expr } }
+
+        pub rule const_expr() -> Node<ConstExpr> = precedence!{
+            start:position!() expr:@ end:position!() { Node { start, end, expr } }
+            --
+            x:(@) _? "|" _? y:@     { binary(BinOp::Or, x, y) }
+            --
+            x:(@) _? "^" _? y:@     { binary(BinOp::Xor, x, y) }
+            --
+            x:(@) _? "&" _? y:@     { binary(BinOp::And, x, y) }
+            --
+            x:(@) _? "<<" _? y:@    { binary(BinOp::Shl, x, y) }
+            x:(@) _? ">>" _? y:@    { binary(BinOp::Shr, x, y) }
+            --
+            x:(@) _? "+" _? y:@     { binary(BinOp::Add, x, y) }
+            x:(@) _? "-" _? y:@     { binary(BinOp::Sub, x, y) }
+            --
+            x:(@) _? "*" _? y:@     { binary(BinOp::Mul, x, y) }
+            x:(@) _? "/" _? y:@     { binary(BinOp::Div, x, y) }
+            x:(@) _? "%" _? y:@     { binary(BinOp::Mod, x, y) }
+            --
+            "-" _? x:@              { ConstExpr::Neg(Box::new(x)) }
+            "~" _? x:@              { ConstExpr::Not(Box::new(x)) }
+            --
+            e:const_operand()       { e.expr }
+        }
+
+        // Operand that needs no parentheses around it, as in `#-OFFSET`.
+        rule const_operand() -> Node<ConstExpr>
+        = "(" _? e:const_expr() _? ")"
+        { e }
+        / start:position!() "-" x:const_operand() end:position!()
+        { Node { start, end, expr: ConstExpr::Neg(Box::new(x)) } }
+        / start:position!() m:magnitude() end:position!()
+        {? i64::try_from(m).map(|i| Node { start, end, expr: ConstExpr::Int(i) }).or(Err("integer literal")) }
+        / start:position!() amp() s:symbol() end:position!()
+        { Node { start, end, expr: ConstExpr::AddressOf(s) } }
+        / start:position!() "\\" s:ident() end:position!()
+        { Node { start, end, expr: ConstExpr::MacroParam(s) } }
+        / start:position!() s:$(alpha() alphanum()*) end:position!()
+        { Node { start, end, expr: ConstExpr::Name(s.to_string()) } }
+
+        // `@table+8`, a symbol with an offset.
+        rule symbol_offset() -> Node<ConstExpr>
+        = start:position!() s:symbol() mid:position!() op:$(sign()) y:const_operand() end:position!()
+        {
+            let x = Node { start, end: mid, expr: ConstExpr::Name(s) };
+            let op = if op == "+" { BinOp::Add } else { BinOp::Sub };
+
+            Node { start, end, expr: binary(op, x, y) }
+        }
+
         pub rule token() -> Node<Token> = precedence!{
             start:position!() expr:@ end:position!() { Node{ start, end, expr } }
             --
-            at() s:ident()      { Token::Ident(s) }
+            at() e:symbol_offset() { Token::Expr(e) }
+            --
+            at() s:symbol()     { Token::Ident(s) }
+            --
+            amp() s:symbol()    { Token::AddressOf(s) }
             --
             dot() s:ident()     { Token::Ident(s) }
             --
@@ -82,23 +252,52 @@ peg::parser! {
             --
             s:string()          { Token::String(s) }
             --
-            sharp() i:int()     { Token::Int(i as i32) }
+            sharp() f:float()   { Token::Float(f) }
+            --
+            sharp() i:int()     { Token::Int(i) }
+            --
+            sharp() e:const_operand() { Token::Expr(e) }
             --
-            sharp() f:float()   { Token::Float(f as f32) }
+            dollar() "f" r:uint() { Token::FloatRegister(r as usize) }
             --
             dollar() r:uint()   { Token::Register(r as usize) }
+            --
+            dollar() s:ident()  { Token::RegisterName(s) }
+            --
+            a:address()         { Token::Address { base: a.0, offset: a.1 } }
+            --
+            o:frame_address()   { Token::FrameAddress { offset: o } }
+            --
+            "\\" s:ident()       { Token::MacroParam(s) }
         }
 
-        pub rule code_expression() -> Node<Expression> = precedence!{
-            start:position!() expr:@ end:position!()  { Node{ start, end, expr } }
-            label:label_declare()? __? op:ident() _? args:token() ** _ __?
+        rule name() -> Node<String>
+        = start:position!() expr:ident() end:position!()
+        { Node { start, end, expr } }
+
+        rule directive() -> Expression
+            = ".func" _ name:name() "(" _? params:name() ** (_? "," _?) _? ")"
+            { Expression::Func(name, params) }
+            / ".endfunc"
+            { Expression::EndFunc }
+            / ".macro" _ name:name() params:(_ p:(name() ++ (_? "," _?)) { p })?
+            { Expression::Macro(name, params.unwrap_or_default()) }
+            / ".endm"
+            { Expression::EndMacro }
+
+        pub rule code_expression() -> Node<Expression>
+            = start:position!() expr:directive() end:position!() _? __?
+            { Node { start, end, expr } }
+            / label:label_node()? __? start:position!() op:ident() _? args:token() ** _ end:position!() _? __?
             {
-                match label {
+                let expr = match label {
                     None => Expression::Call(op, args),
                     Some(lbl) => Expression::Label(lbl, Box::new(Expression::Call(op, args))),
-                }
+                };
+
+                Node { start, end, expr }
             }
-        }
+
         rule data_expression() -> Node<Declare> =  precedence!{
             start:position!() expr:@  end:position!()  { Node{ start, end, expr } }
             label:token() _ ".integer" _ c:token() __
@@ -106,6 +305,18 @@ peg::parser! {
             --
             label:token() _ ".asciiz" _ c:token()  __
             { Declare::ConstString(label, c) }
+            --
+            label:token() _ ".ascii" _ c:token()  __
+            { Declare::ConstAscii(label, c) }
+            --
+            label:token() _ ".bytes" _ values:token() ++ (_? "," _?) __
+            { Declare::ConstBytes(label, values) }
+            --
+            ".jumptable" _ label:token() _ targets:token() ++ (_? "," _?) __
+            { Declare::JumpTable(label, targets) }
+            --
+            ".equ" _ name:name() _ sharp()? e:const_expr() __
+            { Declare::Equ(name, e) }
         }
 
         rule data_section() -> Vec<Node<Declare>>
@@ -133,6 +344,16 @@ mod test {
         assert_eq!(Ok(-100), assembler::int("-100"));
         assert!(assembler::int("-100-100").is_err());
         assert!(assembler::int("-100.100").is_err());
+
+        assert_eq!(Ok(255), assembler::int("0xFF"));
+        assert_eq!(Ok(-10), assembler::int("-0b1010"));
+        assert_eq!(Ok(15), assembler::int("0o17"));
+        assert_eq!(Ok(1_000_000), assembler::int("1_000_000"));
+        assert_eq!(Ok(65), assembler::int("'A'"));
+        assert_eq!(Ok(i64::MIN), assembler::int("-9223372036854775808"));
+        assert!(assembler::int("9223372036854775808").is_err());
+        assert!(assembler::int("0x").is_err());
+        assert!(assembler::int("_1").is_err());
     }
 
     #[test]
@@ -140,11 +361,78 @@ mod test {
         assert_eq!(Ok(100.), assembler::float("100."));
         assert_eq!(Ok(100.100), assembler::float("100.100"));
         assert_eq!(Ok(-100.100), assembler::float("-100.100"));
+        assert!(assembler::float("100").is_err());
+    }
+
+    #[test]
+    fn string() {
+        assert_eq!(Ok(b"Hello, world!\n".to_vec()), assembler::string(r#""Hello, world!\n""#));
+        assert_eq!(Ok(b"tab\t'q' \"dq\" \\".to_vec()), assembler::string(r#"'tab\t\'q\' "dq" \\'"#));
+        assert_eq!(Ok("\0A\u{e9}€".as_bytes().to_vec()), assembler::string(r#""\0\x41\u{e9}€""#));
+        assert_eq!(Ok(vec![0x80, b'a', 0xFF]), assembler::string(r#""\x80a\xff""#));
+        assert_eq!(Ok(vec![]), assembler::string("''"));
+
+        assert!(assembler::string(r#""\x8""#).is_err());
+        assert!(assembler::string(r#""\u{110000}""#).is_err());
+        assert!(assembler::string("'line\nbreak'").is_err());
+        assert!(assembler::string(r#""unterminated"#).is_err());
+        assert_eq!(Ok(10), assembler::int(r"'\n'"));
+        assert_eq!(Ok(255), assembler::int(r"'\xFF'"));
+    }
+
+    #[test]
+    fn const_expr() {
+        fn shape(node: &Node<ConstExpr>) -> String {
+            match &node.expr {
+                ConstExpr::Int(i) => i.to_string(),
+                ConstExpr::Name(s) => s.clone(),
+                ConstExpr::AddressOf(s) => format!("&{}", s),
+                ConstExpr::MacroParam(s) => format!("\\{}", s),
+                ConstExpr::Neg(x) => format!("-{}", shape(x)),
+                ConstExpr::Not(x) => format!("~{}", shape(x)),
+                ConstExpr::Binary(op, x, y) => format!("({:?} {} {})", op, shape(x), shape(y)),
+            }
+        }
+
+        let expr = assembler::const_expr("A + 2 * -B << 1 | &c").unwrap();
+        assert_eq!("(Or (Shl (Add A (Mul 2 -B)) 1) &c)", shape(&expr));
+        assert_eq!((0, 20), (expr.start, expr.end));
+
+        let expr = assembler::const_expr("(1 - 2) - 3").unwrap();
+        assert_eq!("(Sub (Sub 1 2) 3)", shape(&expr));
+
+        match assembler::token("@table+8").map(|t| t.expr) {
+            Ok(Token::Expr(expr)) => assert_eq!("(Add table 8)", shape(&expr)),
+            other => panic!("{:?}", other),
+        }
+        match assembler::token("#-OFFSET").map(|t| t.expr) {
+            Ok(Token::Expr(expr)) => assert_eq!("-OFFSET", shape(&expr)),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(Ok(Token::Int(-3)), assembler::token("#-3").map(|t| t.expr));
     }
 
     #[test]
     fn token() {
         println!("{:?}", assembler::token("@label"));
+
+        assert_eq!(Ok(Token::Int(3)), assembler::token("#3").map(|t| t.expr));
+        assert_eq!(Ok(Token::Float(2.5)), assembler::token("#2.5").map(|t| t.expr));
+        assert_eq!(Ok(Token::FloatRegister(2)), assembler::token("$f2").map(|t| t.expr));
+        assert_eq!(Ok(Token::RegisterName("n".into())), assembler::token("$n").map(|t| t.expr));
+    }
+
+    #[test]
+    fn address() {
+        assert_eq!(Ok((2, 0)), assembler::address("[$2]"));
+        assert_eq!(Ok((2, 4)), assembler::address("[$2+4]"));
+        assert_eq!(Ok((31, -8)), assembler::address("[$31-8]"));
+        assert!(assembler::address("[$2 4]").is_err());
+        assert!(assembler::address("[#2]").is_err());
+
+        assert_eq!(Ok(0), assembler::frame_address("[bp]"));
+        assert_eq!(Ok(-4), assembler::frame_address("[bp-4]"));
+        assert!(assembler::frame_address("[sp-4]").is_err());
     }
 
     #[test]
@@ -168,9 +456,78 @@ load $0 #1
 sub $2 $0 $1
 ret
 ";
-        let (data, expressions) = assembler::parse(input).expect("err");
+        let (data, _expressions) = assembler::parse(input).expect("err");
         for expr in &data.unwrap() {
             println!("{:?}", expr);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn directive() {
+        let func = assembler::code_expression(".func max(a, b)\n").unwrap();
+        match func.expr {
+            Expression::Func(name, params) => {
+                assert_eq!("max", name.expr);
+                assert_eq!(vec!["a", "b"], params.iter().map(|p| p.expr.as_str()).collect::<Vec<_>>());
+            }
+            expr => panic!("{:?}", expr),
+        }
+
+        assert_eq!(Expression::EndFunc, assembler::code_expression(".endfunc\n").unwrap().expr);
+        assert!(assembler::code_expression(".func f()\n").is_ok());
+
+        match assembler::code_expression(".macro clamp r, lo, hi\n").unwrap().expr {
+            Expression::Macro(name, params) => {
+                assert_eq!("clamp", name.expr);
+                assert_eq!(vec!["r", "lo", "hi"], params.iter().map(|p| p.expr.as_str()).collect::<Vec<_>>());
+            }
+            expr => panic!("{:?}", expr),
+        }
+
+        assert_eq!(Expression::Macro(Node { start: 7, end: 10, expr: "nop".into() }, vec![]),
+            assembler::code_expression(".macro nop\n").unwrap().expr);
+        assert_eq!(Expression::EndMacro, assembler::code_expression(".endm\n").unwrap().expr);
+
+        match assembler::code_expression("skip\\@: jmp @skip\\@ \\r\n").unwrap().expr {
+            Expression::Label(label, call) => {
+                assert_eq!("skip\\@", label.expr);
+                assert_eq!(Expression::Call("jmp".into(), vec![
+                    Node { start: 12, end: 19, expr: Token::Ident("skip\\@".into()) },
+                    Node { start: 20, end: 22, expr: Token::MacroParam("r".into()) },
+                ]), *call);
+            }
+            expr => panic!("{:?}", expr),
+        }
+    }
+
+    const CORPUS: &[(&str, &str)] = &[
+        ("factorial", include_str!("corpus/factorial.asm")),
+        ("greeting", include_str!("corpus/greeting.asm")),
+        ("functions", include_str!("corpus/functions.asm")),
+    ];
+
+    #[test]
+    fn corpus() {
+        use crate::assembler::Parser;
+
+        for (name, source) in CORPUS {
+            let program = Parser::new().process(source)
+                .unwrap_or_else(|errors| panic!("{}: {:?}", name, errors));
+
+            let crlf = source.replace('\n', "\r\n");
+            assert_eq!(Ok(program), Parser::new().process(&crlf), "{} with CRLF", name);
+        }
+    }
+
+    #[test]
+    fn comments() {
+        let (_, code) = assembler::parse(".data ; no data\n.code\nload $0 #1 ;#2\n#! hlt\n\n  ret;\n").unwrap();
+
+        assert_eq!(2, code.len());
+        assert_eq!(Expression::Call("load".into(), vec![
+            Node { start: 27, end: 29, expr: Token::Register(0) },
+            Node { start: 30, end: 32, expr: Token::Int(1) },
+        ]), code[0].expr);
+        assert_eq!(Expression::Call("ret".into(), vec![]), code[1].expr);
+    }
+}
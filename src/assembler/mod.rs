@@ -1,4 +1,7 @@
 mod parser;
 mod lexer;
+mod diagnostic;
+pub mod convention;
 
-pub use parser::Parser;
\ No newline at end of file
+pub use diagnostic::Diagnostic;
+pub use parser::{Parser, ParserError, SymbolTable, SymbolType};
@@ -0,0 +1,98 @@
+use std::{collections::HashMap, convert::TryFrom};
+
+use super::{
+    BinOp,
+    ConstExpr,
+    Node,
+    ParserError,
+    SymbolTable,
+    Token,
+};
+
+/// `.equ` definitions. They are evaluated on use, so a definition may
+/// refer to constants and labels declared after it.
+///
+/// A name evaluates to the value of a constant, the address of data and
+/// the code offset of a label, like `&name`.
+#[derive(Default)]
+pub struct Constants(HashMap<String, Node<ConstExpr>>);
+
+impl Constants {
+    pub fn define(&mut self, name: String, expr: Node<ConstExpr>) {
+        self.0.insert(name, expr);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Definitions in source order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Node<ConstExpr>)> {
+        let mut defs = self.0.iter()
+            .map(|(name, expr)| (name.as_str(), expr))
+            .collect::<Vec<_>>();
+        defs.sort_by_key(|(_, expr)| expr.start);
+
+        defs.into_iter()
+    }
+
+    pub fn eval(&self, expr: &Node<ConstExpr>, st: &SymbolTable) -> Result<i64, ParserError> {
+        self.eval_in(expr, st, &mut Vec::new())
+    }
+
+    fn eval_in<'a>(&'a self, expr: &Node<ConstExpr>, st: &SymbolTable, active: &mut Vec<&'a str>) -> Result<i64, ParserError> {
+        let span = (expr.start, expr.end);
+        let overflow = || ParserError::ConstOverflow { span };
+
+        match &expr.expr {
+            ConstExpr::Int(i) => Ok(*i),
+            ConstExpr::Name(name) => match self.0.get_key_value(name) {
+                Some((name, _)) if active.contains(&name.as_str()) => {
+                    Err(ParserError::ConstCycle { name: name.clone(), span })
+                }
+                Some((name, def)) => {
+                    active.push(name);
+                    let value = self.eval_in(def, st, active);
+                    active.pop();
+
+                    value
+                }
+                None => st.get_address(name)
+                    .or_else(|| st.get_offset(name))
+                    .map(|addr| addr as i64)
+                    .ok_or_else(|| ParserError::ConstUnknown { name: name.clone(), span }),
+            },
+            ConstExpr::AddressOf(name) => st.get_address(name)
+                .or_else(|| st.get_offset(name))
+                .map(|addr| addr as i64)
+                .ok_or_else(|| ParserError::ConstUnknown { name: name.clone(), span }),
+            ConstExpr::MacroParam(param) => {
+                let token = Node { start: expr.start, end: expr.end, expr: Token::MacroParam(param.clone()) };
+
+                Err(ParserError::MacroParamUnknown { token })
+            }
+            ConstExpr::Neg(x) => self.eval_in(x, st, active)?.checked_neg().ok_or_else(overflow),
+            ConstExpr::Not(x) => Ok(!self.eval_in(x, st, active)?),
+            ConstExpr::Binary(op, x, y) => {
+                let (l, r) = (self.eval_in(x, st, active)?, self.eval_in(y, st, active)?);
+                if r == 0 && matches!(op, BinOp::Div | BinOp::Mod) {
+                    return Err(ParserError::ConstDivisionByZero { span });
+                }
+
+                let shift = || u32::try_from(r).ok().filter(|r| *r < 64);
+                match op {
+                    BinOp::Add => l.checked_add(r),
+                    BinOp::Sub => l.checked_sub(r),
+                    BinOp::Mul => l.checked_mul(r),
+                    BinOp::Div => l.checked_div(r),
+                    BinOp::Mod => l.checked_rem(r),
+                    BinOp::Shl => shift().and_then(|r| l.checked_shl(r)),
+                    BinOp::Shr => shift().and_then(|r| l.checked_shr(r)),
+                    BinOp::And => Some(l & r),
+                    BinOp::Or => Some(l | r),
+                    BinOp::Xor => Some(l ^ r),
+                }.ok_or_else(overflow)
+            }
+        }
+    }
+}
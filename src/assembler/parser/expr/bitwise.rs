@@ -0,0 +1,76 @@
+use std::convert::{
+    TryFrom,
+    TryInto,
+};
+
+use super::{
+    Node,
+    Token,
+    Int,
+    Register,
+    ParserError,
+    Instruction,
+};
+
+/// `and $rd $rl $rh`, or with an immediate as last operand `and $rd $rl #n`.
+pub struct Bitwise<E>(pub E);
+
+impl TryFrom<(&str, Vec<Node<Token>>)> for Bitwise<Instruction> {
+    type Error = ParserError;
+
+    fn try_from(value: (&str, Vec<Node<Token>>)) -> Result<Self, Self::Error> {
+        let (op, args) = value;
+
+        if args.len() != 3 {
+            return Err(ParserError::ArgumentCountMismatch { expected: 3, got: args.len() });
+        }
+
+        let rd: Register = (&args[0]).try_into()?;
+        let rl: Register = (&args[1]).try_into()?;
+        let (rd, rl) = (rd.0, rl.0);
+
+        if let Token::Int(_) = args[2].expr {
+            let value = Int::try_from(&args[2])?.0;
+
+            return Ok(Bitwise(match op {
+                "and" => Instruction::ANDI { rd, rl, value },
+                "or" => Instruction::ORI { rd, rl, value },
+                "xor" => Instruction::XORI { rd, rl, value },
+                "shl" => Instruction::SHLI { rd, rl, value },
+                "shr" => Instruction::SHRI { rd, rl, value },
+                "sar" => Instruction::SARI { rd, rl, value },
+                _ => return Err(ParserError::OpUnknown(op.to_string()))
+            }));
+        }
+
+        let rh: Register = (&args[2]).try_into()?;
+        let rh = rh.0;
+
+        Ok(Bitwise(match op {
+            "and" => Instruction::AND { rd, rl, rh },
+            "or" => Instruction::OR { rd, rl, rh },
+            "xor" => Instruction::XOR { rd, rl, rh },
+            "shl" => Instruction::SHL { rd, rl, rh },
+            "shr" => Instruction::SHR { rd, rl, rh },
+            "sar" => Instruction::SAR { rd, rl, rh },
+            _ => return Err(ParserError::OpUnknown(op.to_string()))
+        }))
+    }
+}
+
+pub struct Not<E>(pub E);
+
+impl TryFrom<Vec<Node<Token>>> for Not<Instruction> {
+    type Error = ParserError;
+
+    fn try_from(args: Vec<Node<Token>>) -> Result<Self, Self::Error> {
+        if args.len() != 2 {
+            return Err(ParserError::ArgumentCountMismatch { expected: 2, got: args.len() });
+        }
+
+        let rd: Register = (&args[0]).try_into()?;
+        let rs: Register = (&args[1]).try_into()?;
+
+        Ok(Not(Instruction::NOT { rd: rd.0, rs: rs.0 }))
+    }
+}
@@ -7,6 +7,7 @@ use super::{
     Node,
     Token,
     Ident,
+    Register,
     SymbolTable,
     ParserError,
     Instruction,
@@ -23,9 +24,16 @@ impl TryFrom<(Vec<Node<Token>>, &SymbolTable)> for Call<Instruction> {
             return Err(ParserError::ArgumentCountMismatch { got: args.len(), expected: 1 });
         }
 
+        if let Token::Register(_) = args[0].expr {
+            let r: Register = (&args[0]).try_into()?;
+
+            return Ok(Call(Instruction::CALLR { r: r.0 }));
+        }
+
         let ident: Ident = (&args[0]).try_into()?;
 
-        let value = st.get_offset(&ident.0).expect("should be an integer");
+        let value = st.get_offset(&ident.0)
+            .ok_or_else(|| ParserError::LabelUnknown { label: args[0].clone() })?;
 
         Ok(Call(Instruction::CALL { dst: value }))
     }
@@ -0,0 +1,115 @@
+use std::convert::{
+    TryFrom,
+    TryInto,
+};
+
+use super::{
+    Node,
+    Token,
+    Float,
+    Register,
+    FloatRegister,
+    ParserError,
+    Instruction,
+};
+
+pub struct LoadF<E>(pub E);
+
+impl TryFrom<Vec<Node<Token>>> for LoadF<Instruction> {
+    type Error = ParserError;
+
+    fn try_from(args: Vec<Node<Token>>) -> Result<Self, Self::Error> {
+        if args.len() != 2 {
+            return Err(ParserError::ArgumentCountMismatch { expected: 2, got: args.len() });
+        }
+
+        let fd: FloatRegister = (&args[0]).try_into()?;
+        let value: Float = (&args[1]).try_into()?;
+
+        Ok(LoadF(Instruction::LOADF { fd: fd.0, value: value.0 }))
+    }
+}
+
+pub struct FMath<E>(pub E);
+
+impl TryFrom<(&str, Vec<Node<Token>>)> for FMath<Instruction> {
+    type Error = ParserError;
+
+    fn try_from(value: (&str, Vec<Node<Token>>)) -> Result<Self, Self::Error> {
+        let (op, args) = value;
+
+        if args.len() != 3 {
+            return Err(ParserError::ArgumentCountMismatch { expected: 3, got: args.len() });
+        }
+
+        let f0: FloatRegister = (&args[0]).try_into()?;
+        let f1: FloatRegister = (&args[1]).try_into()?;
+        let f2: FloatRegister = (&args[2]).try_into()?;
+
+        Ok(FMath(match op {
+            "fadd" => Instruction::FADD { fd: f0.0, fl: f1.0, fh: f2.0 },
+            "fsub" => Instruction::FSUB { fd: f0.0, fl: f1.0, fh: f2.0 },
+            "fmul" => Instruction::FMUL { fd: f0.0, fl: f1.0, fh: f2.0 },
+            "fdiv" => Instruction::FDIV { fd: f0.0, fl: f1.0, fh: f2.0 },
+            _ => return Err(ParserError::OpUnknown(op.to_string()))
+        }))
+    }
+}
+
+pub struct FCmp<E>(pub E);
+
+impl TryFrom<(&str, Vec<Node<Token>>)> for FCmp<Instruction> {
+    type Error = ParserError;
+
+    fn try_from(value: (&str, Vec<Node<Token>>)) -> Result<Self, Self::Error> {
+        let (op, args) = value;
+
+        if args.len() != 2 {
+            return Err(ParserError::ArgumentCountMismatch { expected: 2, got: args.len() });
+        }
+
+        let f0: FloatRegister = (&args[0]).try_into()?;
+        let f1: FloatRegister = (&args[1]).try_into()?;
+
+        Ok(FCmp(match op {
+            "feq" => Instruction::FEQ { fl: f0.0, fh: f1.0 },
+            "fneq" => Instruction::FNEQ { fl: f0.0, fh: f1.0 },
+            "fgte" => Instruction::FGTE { fl: f0.0, fh: f1.0 },
+            "flte" => Instruction::FLTE { fl: f0.0, fh: f1.0 },
+            "flt" => Instruction::FLT { fl: f0.0, fh: f1.0 },
+            "fgt" => Instruction::FGT { fl: f0.0, fh: f1.0 },
+            _ => return Err(ParserError::OpUnknown(op.to_string()))
+        }))
+    }
+}
+
+/// `itof $f0 $1` and `ftoi $1 $f0`, destination first.
+pub struct Convert<E>(pub E);
+
+impl TryFrom<(&str, Vec<Node<Token>>)> for Convert<Instruction> {
+    type Error = ParserError;
+
+    fn try_from(value: (&str, Vec<Node<Token>>)) -> Result<Self, Self::Error> {
+        let (op, args) = value;
+
+        if args.len() != 2 {
+            return Err(ParserError::ArgumentCountMismatch { expected: 2, got: args.len() });
+        }
+
+        Ok(Convert(match op {
+            "itof" => {
+                let fd: FloatRegister = (&args[0]).try_into()?;
+                let rs: Register = (&args[1]).try_into()?;
+
+                Instruction::ITOF { fd: fd.0, rs: rs.0 }
+            }
+            "ftoi" => {
+                let rd: Register = (&args[0]).try_into()?;
+                let fs: FloatRegister = (&args[1]).try_into()?;
+
+                Instruction::FTOI { rd: rd.0, fs: fs.0 }
+            }
+            _ => return Err(ParserError::OpUnknown(op.to_string()))
+        }))
+    }
+}
@@ -7,6 +7,7 @@ use super::{
     Node,
     Token,
     Ident,
+    Register,
     SymbolTable,
     ParserError,
     Instruction,
@@ -23,14 +24,26 @@ impl TryFrom<(&str, Vec<Node<Token>>, &SymbolTable)> for Jmp<Instruction> {
             return Err(ParserError::ArgumentCountMismatch { got: args.len(), expected: 1 });
         }
 
+        if let Token::Register(_) = args[0].expr {
+            let r: Register = (&args[0]).try_into()?;
+
+            return match op {
+                "jmp" => Ok(Jmp(Instruction::JMPR { r: r.0 })),
+                _ => Err(ParserError::ArgumentInvalid { token: args[0].clone() }),
+            };
+        }
+
         let ident: Ident = (&args[0]).try_into()?;
 
-        let dst = st.get_offset(&ident.0).expect("should be an integer");
+        let dst = st.get_offset(&ident.0)
+            .ok_or_else(|| ParserError::LabelUnknown { label: args[0].clone() })?;
 
         Ok(Jmp(match op {
             "jmp" => Instruction::JMP { dst },
             "jmpe" => Instruction::JMPE { dst },
             "jmpne" => Instruction::JMPNE { dst },
+            "jmpc" => Instruction::JMPC { dst },
+            "jmpo" => Instruction::JMPO { dst },
             _ => return Err(ParserError::OpUnknown(op.to_string())),
         }))
     }
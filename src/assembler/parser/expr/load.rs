@@ -1,12 +1,17 @@
-use std::convert::TryFrom;
+use std::convert::{
+    TryFrom,
+    TryInto,
+};
 
 use super::{
     Node,
     Token,
+    Int,
     SymbolTable,
     ParserError,
     Instruction,
 };
+use crate::instruction::Width;
 
 pub struct Load<E>(pub E);
 
@@ -20,15 +25,31 @@ impl TryFrom<(Vec<Node<Token>>, &SymbolTable)> for Load<Instruction> {
         }
 
         Ok(Load(match (&args[0].expr, &args[1].expr) {
+            // Like `&label`, a data label gives its address, `.integer`
+            // included: the value is read from memory with `loadw`.
             (Token::Register(r0), Token::Ident(ident)) => {
-                let value = st.get_integer(ident).expect("should be an integer");
+                let addr = st.get_address(ident)
+                    .ok_or_else(|| ParserError::LabelUnknown { label: args[1].clone() })?;
+
+                Instruction::LOAD { rd: *r0, value: addr as i32 }
+            }
+            (Token::Register(r0), Token::AddressOf(ident)) => {
+                let addr = st.get_address(ident)
+                    .or_else(|| st.get_offset(ident))
+                    .ok_or_else(|| ParserError::LabelUnknown { label: args[1].clone() })?;
+
+                Instruction::LOAD { rd: *r0, value: addr as i32 }
+            }
+            (Token::Register(r0), Token::Int(_)) => {
+                let int: Int = (&args[1]).try_into()?;
 
-                Instruction::LOAD { rd: *r0, value }
+                Instruction::LOAD { rd: *r0, value: int.0 }
             }
-            (Token::Register(r0), Token::Int(i)) => {
-                Instruction::LOAD { rd: *r0, value: *i }
+            (Token::Register(r0), Token::FrameAddress { offset }) => {
+                Instruction::LOADBP { width: Width::Word, rd: *r0, offset: *offset }
             }
-            tok => return Err(ParserError::ArgumentInvalid { token: tok.1.clone() })
+            (Token::Register(_), _) => return Err(ParserError::ArgumentInvalid { token: args[1].clone() }),
+            _ => return Err(ParserError::ArgumentInvalid { token: args[0].clone() }),
         }))
     }
 }
\ No newline at end of file
@@ -7,7 +7,8 @@ use super::{
     Node,
     Token,
     Ident,
-    Int,
+    UInt,
+    Register,
     SymbolTable,
     ParserError,
     Instruction,
@@ -27,7 +28,8 @@ impl TryFrom<(Vec<Node<Token>>, &SymbolTable)> for Loop<Instruction> {
 
         let ident: Ident = (&args[0]).try_into()?;
 
-        let value = st.get_offset(&ident.0).expect("should be an integer");
+        let value = st.get_offset(&ident.0)
+            .ok_or_else(|| ParserError::LabelUnknown { label: args[0].clone() })?;
 
         Ok(Loop(Instruction::LOOP { dst: value }))
     }
@@ -45,8 +47,14 @@ impl TryFrom<(Vec<Node<Token>>, &SymbolTable)> for CLoop<Instruction> {
             return Err(ParserError::ArgumentCountMismatch { got: args.len(), expected: 1 });
         }
 
-        let int: Int = (&args[0]).try_into()?;
+        if let Token::Register(_) = args[0].expr {
+            let r: Register = (&args[0]).try_into()?;
 
-        Ok(CLoop(Instruction::CLOOP { count: int.0 as usize }))
+            return Ok(CLoop(Instruction::CLOOPR { r: r.0 }));
+        }
+
+        let count: UInt = (&args[0]).try_into()?;
+
+        Ok(CLoop(Instruction::CLOOP { count: count.0 as usize }))
     }
 }
\ No newline at end of file
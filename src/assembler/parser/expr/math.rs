@@ -19,19 +19,31 @@ impl TryFrom<(&str, Vec<Node<Token>>)> for Math<Instruction> {
     fn try_from(value: (&str, Vec<Node<Token>>)) -> Result<Self, Self::Error> {
         let (op, args) = value;
 
-        if args.len() != 3 {
-            return Err(ParserError::ArgumentCountMismatch { got: args.len(), expected: 3 });
+        let expected = if op == "divmod" { 4 } else { 3 };
+        if args.len() != expected {
+            return Err(ParserError::ArgumentCountMismatch { got: args.len(), expected });
         }
         let r0: Register = (&args[0]).try_into()?;
         let r1: Register = (&args[1]).try_into()?;
         let r2: Register = (&args[2]).try_into()?;
 
+        if op == "divmod" {
+            let r3: Register = (&args[3]).try_into()?;
+
+            return Ok(Math(Instruction::DIVMOD { rq: r0.0, rr: r1.0, rl: r2.0, rh: r3.0 }));
+        }
+
 
         Ok(Math(match op {
             "add" => Instruction::ADD { rd: r0.0, rl: r1.0, rh: r2.0 },
             "sub" => Instruction::SUB { rd: r0.0, rl: r1.0, rh: r2.0 },
             "mul" => Instruction::MUL { rd: r0.0, rl: r1.0, rh: r2.0 },
             "div" => Instruction::DIV { rd: r0.0, rl: r1.0, rh: r2.0 },
+            "mod" => Instruction::MOD { rd: r0.0, rl: r1.0, rh: r2.0 },
+            "divu" => Instruction::DIVU { rd: r0.0, rl: r1.0, rh: r2.0 },
+            "modu" => Instruction::MODU { rd: r0.0, rl: r1.0, rh: r2.0 },
+            "addc" => Instruction::ADDC { rd: r0.0, rl: r1.0, rh: r2.0 },
+            "subb" => Instruction::SUBB { rd: r0.0, rl: r1.0, rh: r2.0 },
             _ => return Err(ParserError::OpUnknown(op.to_string()))
         }))
     }
@@ -0,0 +1,92 @@
+use std::convert::{
+    TryFrom,
+    TryInto,
+};
+
+use super::{
+    Node,
+    Token,
+    Address,
+    Register,
+    ParserError,
+    Instruction,
+};
+use crate::instruction::Width;
+
+fn width(op: &str) -> Result<Width, ParserError> {
+    match op {
+        "loadb" | "storeb" => Ok(Width::Byte),
+        "loadh" | "storeh" => Ok(Width::Half),
+        "loadw" | "storew" => Ok(Width::Word),
+        _ => Err(ParserError::OpUnknown(op.to_string())),
+    }
+}
+
+pub struct LoadM<E>(pub E);
+
+impl TryFrom<(&str, Vec<Node<Token>>)> for LoadM<Instruction> {
+    type Error = ParserError;
+
+    fn try_from(value: (&str, Vec<Node<Token>>)) -> Result<Self, Self::Error> {
+        let (op, args) = value;
+
+        if args.len() != 2 {
+            return Err(ParserError::ArgumentCountMismatch { expected: 2, got: args.len() });
+        }
+
+        let rd: Register = (&args[0]).try_into()?;
+        if let Token::FrameAddress { offset } = args[1].expr {
+            return Ok(LoadM(Instruction::LOADBP { width: width(op)?, rd: rd.0, offset }));
+        }
+
+        let addr: Address = (&args[1]).try_into()?;
+
+        Ok(LoadM(Instruction::LOADM { width: width(op)?, rd: rd.0, base: addr.base, offset: addr.offset }))
+    }
+}
+
+pub struct StoreM<E>(pub E);
+
+impl TryFrom<(&str, Vec<Node<Token>>)> for StoreM<Instruction> {
+    type Error = ParserError;
+
+    fn try_from(value: (&str, Vec<Node<Token>>)) -> Result<Self, Self::Error> {
+        let (op, args) = value;
+
+        if args.len() != 2 {
+            return Err(ParserError::ArgumentCountMismatch { expected: 2, got: args.len() });
+        }
+
+        let rs: Register = (&args[0]).try_into()?;
+        if let Token::FrameAddress { offset } = args[1].expr {
+            return Ok(StoreM(Instruction::STOREBP { width: width(op)?, rs: rs.0, offset }));
+        }
+
+        let addr: Address = (&args[1]).try_into()?;
+
+        Ok(StoreM(Instruction::STOREM { width: width(op)?, rs: rs.0, base: addr.base, offset: addr.offset }))
+    }
+}
+
+/// `push $r` and `pop $r`.
+pub struct Stack<E>(pub E);
+
+impl TryFrom<(&str, Vec<Node<Token>>)> for Stack<Instruction> {
+    type Error = ParserError;
+
+    fn try_from(value: (&str, Vec<Node<Token>>)) -> Result<Self, Self::Error> {
+        let (op, args) = value;
+
+        if args.len() != 1 {
+            return Err(ParserError::ArgumentCountMismatch { expected: 1, got: args.len() });
+        }
+
+        let r: Register = (&args[0]).try_into()?;
+
+        Ok(Stack(match op {
+            "push" => Instruction::PUSH { rs: r.0 },
+            "pop" => Instruction::POP { rd: r.0 },
+            _ => return Err(ParserError::OpUnknown(op.to_string())),
+        }))
+    }
+}
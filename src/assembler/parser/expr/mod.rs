@@ -1,10 +1,15 @@
+mod bitwise;
 mod call;
 mod cmp;
+mod float;
 mod inc;
 mod jmp;
 mod math;
 mod load;
+mod memory;
 mod loops;
+mod switch;
+mod syscall;
 
 use super::{
     Node,
@@ -14,15 +19,24 @@ use super::{
     Instruction,
     token::{
         Int,
+        UInt,
+        Float,
         Ident,
+        Address,
         Register,
+        FloatRegister,
     },
 };
 
+pub use bitwise::{Bitwise, Not};
 pub use call::Call;
 pub use cmp::Cmp;
+pub use float::{LoadF, FMath, FCmp, Convert};
 pub use inc::Inc;
 pub use jmp::Jmp;
 pub use math::Math;
 pub use load::Load;
+pub use memory::{LoadM, StoreM, Stack};
 pub use loops::{Loop, CLoop};
+pub use switch::Switch;
+pub use syscall::Syscall;
@@ -0,0 +1,39 @@
+use std::convert::{
+    TryFrom,
+    TryInto,
+};
+
+use super::{
+    Node,
+    Token,
+    Ident,
+    Register,
+    SymbolTable,
+    ParserError,
+    Instruction,
+};
+
+/// `switch $r @table @default`
+pub struct Switch<E>(pub E);
+
+impl TryFrom<(Vec<Node<Token>>, &SymbolTable)> for Switch<Instruction> {
+    type Error = ParserError;
+
+    fn try_from(value: (Vec<Node<Token>>, &SymbolTable)) -> Result<Self, Self::Error> {
+        let (args, st) = value;
+        if args.len() != 3 {
+            return Err(ParserError::ArgumentCountMismatch { expected: 3, got: args.len() });
+        }
+
+        let r: Register = (&args[0]).try_into()?;
+        let table: Ident = (&args[1]).try_into()?;
+        let default: Ident = (&args[2]).try_into()?;
+
+        let (table, len) = st.get_jump_table(&table.0)
+            .ok_or_else(|| ParserError::LabelUnknown { label: args[1].clone() })?;
+        let default = st.get_offset(&default.0)
+            .ok_or_else(|| ParserError::LabelUnknown { label: args[2].clone() })?;
+
+        Ok(Switch(Instruction::SWITCH { r: r.0, table, len, default }))
+    }
+}
@@ -0,0 +1,28 @@
+use std::convert::{
+    TryFrom,
+    TryInto,
+};
+
+use super::{
+    Node,
+    Token,
+    UInt,
+    ParserError,
+    Instruction,
+};
+
+pub struct Syscall<E>(pub E);
+
+impl TryFrom<Vec<Node<Token>>> for Syscall<Instruction> {
+    type Error = ParserError;
+
+    fn try_from(args: Vec<Node<Token>>) -> Result<Self, Self::Error> {
+        if args.len() != 1 {
+            return Err(ParserError::ArgumentCountMismatch { expected: 1, got: args.len() });
+        }
+
+        let n: UInt = (&args[0]).try_into()?;
+
+        Ok(Syscall(Instruction::SYSCALL { n: n.0 as usize }))
+    }
+}
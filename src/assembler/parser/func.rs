@@ -0,0 +1,169 @@
+use std::collections::{
+    BTreeSet,
+    HashMap,
+};
+
+use super::{
+    Node,
+    Notes,
+    Token,
+    TokenNode,
+    Expression,
+    Parser,
+    ParserError,
+};
+use crate::assembler::convention::{
+    ARGUMENT_REGISTERS,
+    CALLEE_SAVED,
+};
+
+/// Code section after `.func` expansion: one `Op` per instruction, with
+/// the macro expansion notes of the source line.
+pub enum Line {
+    Label(Node<String>, Notes),
+    Op(Node<(String, Vec<TokenNode>)>, Notes),
+}
+
+fn op(start: usize, end: usize, name: &str, args: Vec<Token>, notes: &Notes) -> Line {
+    let args = args.into_iter()
+        .map(|expr| Node { start, end, expr })
+        .collect();
+
+    Line::Op(Node { start, end, expr: (name.to_string(), args) }, notes.clone())
+}
+
+/// Arguments of a call, possibly behind labels.
+fn call_args(expr: &Expression) -> &[TokenNode] {
+    match expr {
+        Expression::Call(_, args) => args,
+        Expression::Label(_, inner) => call_args(inner),
+        _ => &[],
+    }
+}
+
+impl Parser {
+    /// Replaces `.func`/`.endfunc` blocks by plain code with the prologue
+    /// and epilogues described in `convention`.
+    pub(super) fn expand_functions(&mut self, code_segment: Vec<(Node<Expression>, Notes)>) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut code = code_segment.into_iter();
+
+        while let Some((node, notes)) = code.next() {
+            self.context = notes;
+
+            match node.expr {
+                Expression::Func(name, params) => {
+                    let mut body = Vec::new();
+                    let mut closed = false;
+
+                    for (inner, inner_notes) in code.by_ref() {
+                        match inner.expr {
+                            Expression::EndFunc => {
+                                closed = true;
+                                break;
+                            }
+                            Expression::Func(..) => {
+                                let err = ParserError::FuncNested;
+
+                                self.error_in((inner.start, inner.end), err, inner_notes);
+                            }
+                            _ => body.push((inner, inner_notes)),
+                        }
+                    }
+
+                    if !closed {
+                        self.error((name.start, name.end), ParserError::FuncUnterminated(name.expr.clone()));
+                    }
+
+                    self.expand_function(&mut lines, (node.start, node.end), name, params, body);
+                }
+                Expression::EndFunc => self.error((node.start, node.end), ParserError::EndFuncUnmatched),
+                expr => self.expand_line(&mut lines, Node { start: node.start, end: node.end, expr }, &HashMap::new(), &[]),
+            }
+        }
+
+        self.context = Vec::new();
+
+        lines
+    }
+
+    fn expand_function(
+        &mut self,
+        lines: &mut Vec<Line>,
+        (start, end): (usize, usize),
+        name: Node<String>,
+        params: Vec<Node<String>>,
+        body: Vec<(Node<Expression>, Notes)>,
+    ) {
+        if params.len() > ARGUMENT_REGISTERS.count() {
+            let err = ParserError::TooManyParams { name: name.expr.clone(), got: params.len() };
+
+            self.error((start, end), err);
+        }
+
+        let aliases = params.into_iter()
+            .zip(ARGUMENT_REGISTERS)
+            .map(|(param, r)| (param.expr, r))
+            .collect::<HashMap<_, _>>();
+
+        let saved = body.iter()
+            .flat_map(|(node, _)| call_args(&node.expr))
+            .filter_map(|token| match token.expr {
+                Token::Register(r) if CALLEE_SAVED.contains(&r) => Some(r),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let notes = self.context.clone();
+
+        lines.push(Line::Label(name, notes.clone()));
+        for r in &saved {
+            lines.push(op(start, end, "push", vec![Token::Register(*r)], &notes));
+        }
+
+        for (node, notes) in body {
+            self.context = notes;
+            self.expand_line(lines, node, &aliases, &saved);
+        }
+    }
+
+    /// Emits a plain line, resolving `$name` registers through `aliases` and
+    /// reloading `saved` registers before a `ret`. Errors and lines carry the
+    /// notes in `self.context`.
+    fn expand_line(&mut self, lines: &mut Vec<Line>, node: Node<Expression>, aliases: &HashMap<String, usize>, saved: &[usize]) {
+        let (op_name, args) = match node.expr {
+            Expression::Call(op_name, args) => (op_name, args),
+            Expression::Label(label, inner) => {
+                lines.push(Line::Label(label, self.context.clone()));
+
+                return self.expand_line(lines, Node { start: node.start, end: node.end, expr: *inner }, aliases, saved);
+            }
+            Expression::Func(..) | Expression::EndFunc | Expression::Macro(..) | Expression::EndMacro => return,
+        };
+
+        let mut resolved = Vec::with_capacity(args.len());
+        for token in args {
+            match &token.expr {
+                Token::RegisterName(name) => match aliases.get(name) {
+                    Some(r) => resolved.push(Node { start: token.start, end: token.end, expr: Token::Register(*r) }),
+                    None => return self.error((node.start, node.end), ParserError::RegisterUnknown { token }),
+                },
+                _ => resolved.push(token),
+            }
+        }
+
+        if op_name == "ret" {
+            for (i, r) in saved.iter().enumerate() {
+                let offset = -4 * (i as i32 + 1);
+
+                let args = vec![Token::Register(*r), Token::FrameAddress { offset }];
+
+                lines.push(op(node.start, node.end, "loadw", args, &self.context));
+            }
+        }
+
+        lines.push(Line::Op(Node { start: node.start, end: node.end, expr: (op_name, resolved) }, self.context.clone()));
+    }
+}
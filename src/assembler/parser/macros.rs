@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use super::{
+    ConstExpr,
+    Node,
+    Token,
+    TokenNode,
+    Expression,
+    Parser,
+    ParserError,
+};
+
+/// Secondary locations attached to an error: the invocations a line was
+/// expanded from, innermost first, and where a macro was defined.
+pub type Notes = Vec<(usize, usize, String)>;
+
+struct Macro {
+    name: Node<String>,
+    params: Vec<Node<String>>,
+    body: Vec<Node<Expression>>,
+}
+
+/// State of the expansion pass.
+struct Expansion<'a> {
+    macros: &'a HashMap<String, Macro>,
+    /// Macros being expanded, outermost first.
+    active: Vec<&'a str>,
+    /// Number of expansions so far, `\@` becomes `__m<count>`.
+    count: usize,
+    lines: Vec<(Node<Expression>, Notes)>,
+}
+
+impl Parser {
+    /// Collects `.macro`/`.endm` definitions and replaces their invocations
+    /// by the body, with `\param` bound to the arguments and `\@` to a suffix
+    /// unique to each expansion, so local labels do not clash with each
+    /// other or with labels written by hand.
+    ///
+    /// Expanded lines keep the span of the definition, the invocations
+    /// they come from are returned alongside as notes.
+    pub(super) fn expand_macros(&mut self, code_segment: Vec<Node<Expression>>) -> Vec<(Node<Expression>, Notes)> {
+        let mut macros = HashMap::new();
+        let mut rest = Vec::new();
+        let mut code = code_segment.into_iter();
+
+        while let Some(node) = code.next() {
+            match node.expr {
+                Expression::Macro(name, params) => {
+                    let mut body = Vec::new();
+                    let mut closed = false;
+
+                    for inner in code.by_ref() {
+                        match inner.expr {
+                            Expression::EndMacro => {
+                                closed = true;
+                                break;
+                            }
+                            Expression::Macro(..) => self.error((inner.start, inner.end), ParserError::MacroNested),
+                            _ => body.push(inner),
+                        }
+                    }
+
+                    if !closed {
+                        self.error((name.start, name.end), ParserError::MacroUnterminated(name.expr.clone()));
+                    }
+
+                    if macros.contains_key(&name.expr) {
+                        self.error((name.start, name.end), ParserError::MacroDuplicate(name.expr.clone()));
+                    } else {
+                        macros.insert(name.expr.clone(), Macro { name, params, body });
+                    }
+                }
+                Expression::EndMacro => self.error((node.start, node.end), ParserError::EndMacroUnmatched),
+                _ => rest.push(node),
+            }
+        }
+
+        let mut expansion = Expansion { macros: &macros, active: Vec::new(), count: 0, lines: Vec::new() };
+        for node in rest {
+            self.expand_invocation(&mut expansion, node, &Vec::new());
+        }
+
+        let mut lines = Vec::with_capacity(expansion.lines.len());
+        for (node, notes) in expansion.lines {
+            if self.check_expanded(&node, &notes) {
+                lines.push((node, notes));
+            }
+        }
+
+        lines
+    }
+
+    fn expand_invocation<'a>(&mut self, expansion: &mut Expansion<'a>, node: Node<Expression>, notes: &Notes) {
+        let (start, end) = (node.start, node.end);
+
+        let (name, args) = match node.expr {
+            Expression::Call(name, args) if expansion.macros.contains_key(&name) => (name, args),
+            Expression::Label(label, inner) => {
+                let (first, errors) = (expansion.lines.len(), self.errors.len());
+                self.expand_invocation(expansion, Node { start, end, expr: *inner }, notes);
+
+                // The label names the first line of the expansion. A failed
+                // expansion has been reported already.
+                match expansion.lines.get_mut(first) {
+                    Some((line, _)) => {
+                        let expr = std::mem::replace(&mut line.expr, Expression::EndMacro);
+                        line.expr = Expression::Label(label, Box::new(expr));
+                    }
+                    None if self.errors.len() == errors => {
+                        self.error_in((label.start, label.end), ParserError::LabelWithoutCode(label.expr), notes.clone());
+                    }
+                    None => {}
+                }
+
+                return;
+            }
+            expr => return expansion.lines.push((Node { start, end, expr }, notes.clone())),
+        };
+
+        let macros = expansion.macros;
+        let (name, mac) = macros.get_key_value(&name).unwrap();
+        let defined = (mac.name.start, mac.name.end, format!("macro `{}` defined here", name));
+
+        // Without conditional assembly a recursive macro never stops
+        // expanding, so the limit is not to recurse at all.
+        if expansion.active.contains(&name.as_str()) {
+            let notes = notes.iter().cloned().chain(Some(defined)).collect();
+
+            return self.error_in((start, end), ParserError::MacroRecursion(name.clone()), notes);
+        }
+
+        if args.len() != mac.params.len() {
+            let err = ParserError::ArgumentCountMismatch { expected: mac.params.len(), got: args.len() };
+            let notes = notes.iter().cloned().chain(Some(defined)).collect();
+
+            return self.error_in((start, end), err, notes);
+        }
+
+        expansion.count += 1;
+        let unique = format!("__m{}", expansion.count);
+        let bindings = mac.params.iter()
+            .map(|param| param.expr.as_str())
+            .zip(args)
+            .collect::<HashMap<_, _>>();
+
+        let notes = Some((start, end, format!("in expansion of macro `{}`", name)))
+            .into_iter()
+            .chain(notes.iter().cloned())
+            .collect::<Notes>();
+
+        expansion.active.push(name);
+        for line in &mac.body {
+            match substitute(line.expr.clone(), &bindings, &unique) {
+                Ok(expr) => self.expand_invocation(expansion, Node { start: line.start, end: line.end, expr }, &notes),
+                Err(err) => self.error_in((line.start, line.end), err, notes.clone()),
+            }
+        }
+        expansion.active.pop();
+    }
+
+    /// Reports `\param` and `\@` left after expansion, i.e. used outside of
+    /// a macro or naming a parameter the macro does not have.
+    fn check_expanded(&mut self, node: &Node<Expression>, notes: &Notes) -> bool {
+        let mut expr = &node.expr;
+
+        while let Expression::Label(label, inner) = expr {
+            if label.expr.contains("\\@") {
+                self.error_in((label.start, label.end), ParserError::MacroLocalOutside, notes.clone());
+            }
+
+            expr = inner;
+        }
+
+        let args = match expr {
+            Expression::Call(_, args) => args,
+            _ => return true,
+        };
+
+        for token in args {
+            match &token.expr {
+                Token::MacroParam(_) => {
+                    self.error_in((node.start, node.end), ParserError::MacroParamUnknown { token: token.clone() }, notes.clone());
+
+                    return false;
+                }
+                Token::Ident(name) | Token::AddressOf(name) if name.contains("\\@") => {
+                    self.error_in((token.start, token.end), ParserError::MacroLocalOutside, notes.clone());
+                }
+                _ => {}
+            }
+        }
+
+        true
+    }
+}
+
+fn substitute(expr: Expression, bindings: &HashMap<&str, TokenNode>, unique: &str) -> Result<Expression, ParserError> {
+    Ok(match expr {
+        Expression::Call(op, args) => Expression::Call(
+            op,
+            args.into_iter().map(|arg| substitute_token(arg, bindings, unique)).collect::<Result<_, _>>()?,
+        ),
+        Expression::Label(label, inner) => {
+            let label = Node { start: label.start, end: label.end, expr: label.expr.replace("\\@", unique) };
+
+            Expression::Label(label, Box::new(substitute(*inner, bindings, unique)?))
+        }
+        expr => expr,
+    })
+}
+
+fn substitute_token(token: TokenNode, bindings: &HashMap<&str, TokenNode>, unique: &str) -> Result<TokenNode, ParserError> {
+    let Node { start, end, expr } = token;
+
+    let expr = match expr {
+        Token::MacroParam(param) => match bindings.get(param.as_str()) {
+            Some(arg) => return Ok(arg.clone()),
+            None => Token::MacroParam(param),
+        },
+        Token::Ident(name) => Token::Ident(name.replace("\\@", unique)),
+        Token::AddressOf(name) => Token::AddressOf(name.replace("\\@", unique)),
+        Token::Expr(e) => Token::Expr(substitute_const(e, bindings, unique)?),
+        expr => expr,
+    };
+
+    Ok(Node { start, end, expr })
+}
+
+/// `\param` inside a constant expression takes the value of an integer,
+/// label or expression argument.
+fn substitute_const(e: Node<ConstExpr>, bindings: &HashMap<&str, TokenNode>, unique: &str) -> Result<Node<ConstExpr>, ParserError> {
+    let Node { start, end, expr } = e;
+    let boxed = |x: Box<Node<ConstExpr>>| substitute_const(*x, bindings, unique).map(Box::new);
+
+    let expr = match expr {
+        ConstExpr::MacroParam(param) => match bindings.get(param.as_str()) {
+            Some(arg) => match &arg.expr {
+                Token::Int(i) => ConstExpr::Int(*i),
+                Token::Ident(name) => ConstExpr::Name(name.clone()),
+                Token::AddressOf(name) => ConstExpr::AddressOf(name.clone()),
+                Token::Expr(e) => e.expr.clone(),
+                _ => return Err(ParserError::ArgumentInvalid { token: arg.clone() }),
+            },
+            None => {
+                let token = Node { start, end, expr: Token::MacroParam(param) };
+
+                return Err(ParserError::MacroParamUnknown { token });
+            }
+        },
+        ConstExpr::Name(name) => ConstExpr::Name(name.replace("\\@", unique)),
+        ConstExpr::AddressOf(name) => ConstExpr::AddressOf(name.replace("\\@", unique)),
+        ConstExpr::Neg(x) => ConstExpr::Neg(boxed(x)?),
+        ConstExpr::Not(x) => ConstExpr::Not(boxed(x)?),
+        ConstExpr::Binary(op, x, y) => ConstExpr::Binary(op, boxed(x)?, boxed(y)?),
+        expr => expr,
+    };
+
+    Ok(Node { start, end, expr })
+}
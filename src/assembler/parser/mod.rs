@@ -4,117 +4,413 @@ use super::lexer::{
     Declare,
     TokenNode,
     Expression,
+    BinOp,
+    ConstExpr,
     assembler::parse,
 };
 
-use std::convert::TryInto;
+use std::{
+    fmt,
+    convert::{TryFrom, TryInto},
+};
 
+mod constant;
 mod expr;
+mod func;
+mod macros;
 mod token;
 mod symbol;
 
-use symbol::{
+use constant::Constants;
+use func::Line;
+use macros::Notes;
+use token::Int;
+
+pub use symbol::{
     SymbolType,
     SymbolTable,
 };
-use crate::instruction::Instruction;
+use super::Diagnostic;
+use crate::{
+    program::Program,
+    instruction::Instruction,
+};
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ParserError {
     OpUnknown(String),
-    ArgumentInvalid { token: Token },
+    ArgumentInvalid { token: TokenNode },
     ArgumentCountMismatch { expected: usize, got: usize },
+    LabelUnknown { label: TokenNode },
+    LabelDuplicate(String),
+    RegisterUnknown { token: TokenNode },
+    IntOutOfRange { token: TokenNode },
+    IntNegative { token: TokenNode },
+    ByteOutOfRange { token: TokenNode },
+    ConstUnknown { name: String, span: (usize, usize) },
+    ConstCycle { name: String, span: (usize, usize) },
+    ConstOverflow { span: (usize, usize) },
+    ConstDivisionByZero { span: (usize, usize) },
+    TooManyParams { name: String, got: usize },
+    FuncNested,
+    FuncUnterminated(String),
+    EndFuncUnmatched,
+    MacroNested,
+    MacroUnterminated(String),
+    EndMacroUnmatched,
+    MacroDuplicate(String),
+    MacroRecursion(String),
+    MacroParamUnknown { token: TokenNode },
+    MacroLocalOutside,
+    LabelWithoutCode(String),
+}
+
+impl ParserError {
+    /// Source range of the offending token, when the error is about one.
+    pub fn span(&self) -> Option<(usize, usize)> {
+        match self {
+            ParserError::ArgumentInvalid { token } => Some((token.start, token.end)),
+            ParserError::LabelUnknown { label } => Some((label.start, label.end)),
+            ParserError::RegisterUnknown { token } => Some((token.start, token.end)),
+            ParserError::IntOutOfRange { token } => Some((token.start, token.end)),
+            ParserError::IntNegative { token } => Some((token.start, token.end)),
+            ParserError::ByteOutOfRange { token } => Some((token.start, token.end)),
+            ParserError::MacroParamUnknown { token } => Some((token.start, token.end)),
+            ParserError::ConstUnknown { span, .. } |
+            ParserError::ConstCycle { span, .. } |
+            ParserError::ConstOverflow { span } |
+            ParserError::ConstDivisionByZero { span } => Some(*span),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParserError::OpUnknown(op) => write!(f, "unknown instruction `{}`", op),
+            ParserError::ArgumentInvalid { token } => write!(f, "invalid argument {:?}", token.expr),
+            ParserError::ArgumentCountMismatch { expected, got } => {
+                write!(f, "expected {} argument(s), got {}", expected, got)
+            }
+            ParserError::LabelUnknown { label } => match &label.expr {
+                Token::Ident(name) | Token::AddressOf(name) => write!(f, "unknown label `{}`", name),
+                tok => write!(f, "unknown label {:?}", tok),
+            },
+            ParserError::LabelDuplicate(label) => write!(f, "label `{}` is already defined", label),
+            ParserError::RegisterUnknown { token } => match &token.expr {
+                Token::RegisterName(name) => write!(f, "unknown register `${}`", name),
+                tok => write!(f, "unknown register {:?}", tok),
+            },
+            ParserError::IntOutOfRange { token } => match &token.expr {
+                Token::Int(i) => write!(f, "integer {} does not fit into 32 bits", i),
+                tok => write!(f, "integer {:?} does not fit into 32 bits", tok),
+            },
+            ParserError::IntNegative { token } => match &token.expr {
+                Token::Int(i) => write!(f, "integer {} must not be negative", i),
+                tok => write!(f, "integer {:?} must not be negative", tok),
+            },
+            ParserError::ByteOutOfRange { token } => match &token.expr {
+                Token::Int(i) => write!(f, "integer {} does not fit into a byte", i),
+                tok => write!(f, "integer {:?} does not fit into a byte", tok),
+            },
+            ParserError::ConstUnknown { name, .. } => write!(f, "unknown name `{}`", name),
+            ParserError::ConstCycle { name, .. } => write!(f, "`{}` is defined in terms of itself", name),
+            ParserError::ConstOverflow { .. } => write!(f, "constant expression overflows"),
+            ParserError::ConstDivisionByZero { .. } => write!(f, "division by zero in constant expression"),
+            ParserError::TooManyParams { name, got } => {
+                write!(f, "function `{}` takes {} parameters, at most 7 fit in argument registers", name, got)
+            }
+            ParserError::FuncNested => write!(f, "`.func` cannot be nested"),
+            ParserError::FuncUnterminated(name) => write!(f, "function `{}` has no `.endfunc`", name),
+            ParserError::EndFuncUnmatched => write!(f, "`.endfunc` without `.func`"),
+            ParserError::MacroNested => write!(f, "`.macro` cannot be nested"),
+            ParserError::MacroUnterminated(name) => write!(f, "macro `{}` has no `.endm`", name),
+            ParserError::EndMacroUnmatched => write!(f, "`.endm` without `.macro`"),
+            ParserError::MacroDuplicate(name) => write!(f, "macro `{}` is already defined", name),
+            ParserError::MacroRecursion(name) => write!(f, "macro `{}` expands to itself", name),
+            ParserError::MacroParamUnknown { token } => match &token.expr {
+                Token::MacroParam(name) => write!(f, "unknown macro parameter `\\{}`", name),
+                tok => write!(f, "unknown macro parameter {:?}", tok),
+            },
+            ParserError::MacroLocalOutside => write!(f, "`\\@` can only be used inside a macro"),
+            ParserError::LabelWithoutCode(label) => write!(f, "label `{}` has no instruction to name", label),
+        }
+    }
 }
 
+#[derive(Default)]
 pub struct Parser {
     st: SymbolTable,
-    instructions: Vec<Result<Instruction, ParserError>>,
+    data: Vec<u8>,
+    instructions: Vec<Instruction>,
+    jump_tables: Vec<(usize, Vec<TokenNode>)>,
+    constants: Constants,
+    /// Expansion notes of the line being processed, see `expand_macros`.
+    context: Notes,
+    errors: Vec<(usize, usize, ParserError, Notes)>,
 }
 
 impl Parser {
     pub fn new() -> Self {
         Self {
             st: SymbolTable::new(),
+            data: Vec::new(),
             instructions: Vec::new(),
+            jump_tables: Vec::new(),
+            constants: Constants::default(),
+            context: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
-    pub fn process(mut self, code: &str) -> Vec<Instruction> {
-        let (data_segment, code_segment) = parse(code).expect("ok");
+    /// Assembles `code`, reporting every error found instead of stopping
+    /// at the first one. Syntax errors abort before any other check.
+    pub fn process(mut self, code: &str) -> Result<Program, Vec<Diagnostic>> {
+        let (data_segment, code_segment) = parse(code).map_err(|err| {
+            let offset = err.location.offset;
+            let end = offset + code[offset..].chars().next().map_or(1, char::len_utf8);
+
+            vec![Diagnostic::new(code, offset, end, format!("syntax error, expected {}", err.expected))]
+        })?;
 
         let data_segment = data_segment.unwrap_or_default();
 
         self.process_data_segment(data_segment);
         self.process_code_segment(code_segment);
+        self.process_jump_tables();
+        self.process_constants();
+
+        if !self.errors.is_empty() {
+            // A broken `.equ` is reported once, not at every use.
+            self.errors.sort_by_key(|(start, _, _, _)| *start);
+            self.errors.dedup();
+
+            return Err(self.errors.into_iter()
+                .map(|(start, end, err, notes)| {
+                    notes.into_iter().fold(
+                        Diagnostic::new(code, start, end, err.to_string()),
+                        |diagnostic, (start, end, note)| diagnostic.with_note(code, start, end, note),
+                    )
+                })
+                .collect());
+        }
+
+        Ok(Program {
+            instructions: self.instructions,
+            data: self.data,
+            symbols: self.st,
+        })
+    }
+
+    fn error(&mut self, node_span: (usize, usize), err: ParserError) {
+        let notes = self.context.clone();
 
+        self.error_in(node_span, err, notes);
+    }
 
-        self.instructions.into_iter().map(|instr| {
-            println!("{:?}", instr);
+    fn error_in(&mut self, node_span: (usize, usize), err: ParserError, notes: Notes) {
+        let (start, end) = err.span().unwrap_or(node_span);
 
-            instr.unwrap()
-        }).collect::<Vec<_>>()
+        self.errors.push((start, end, err, notes));
     }
 
     fn process_data_segment(&mut self, data_segment: Vec<Node<Declare>>) {
         for decl in data_segment {
-            self.process_data_decl(decl.expr);
+            if let Err(err) = self.process_data_decl(decl.expr) {
+                self.error((decl.start, decl.end), err);
+            }
+        }
+    }
+
+    fn declare(&mut self, label: String, symbol: SymbolType) -> Result<(), ParserError> {
+        if self.st.contains(&label) || self.constants.contains(&label) {
+            return Err(ParserError::LabelDuplicate(label));
+        }
+
+        self.st.add(label, symbol);
+
+        Ok(())
+    }
+
+    /// Evaluates a constant expression operand to an integer token.
+    fn resolve(&self, token: TokenNode) -> Result<TokenNode, ParserError> {
+        match &token.expr {
+            Token::Expr(expr) => {
+                let value = self.constants.eval(expr, &self.st)?;
+
+                Ok(Node { start: token.start, end: token.end, expr: Token::Int(value) })
+            }
+            _ => Ok(token),
         }
     }
 
-    fn process_data_decl(&mut self, decl: Declare) {
+    fn process_data_decl(&mut self, decl: Declare) -> Result<(), ParserError> {
+        let decl = match decl {
+            Declare::ConstI64(label, value) => Declare::ConstI64(label, self.resolve(value)?),
+            Declare::ConstBytes(label, values) => Declare::ConstBytes(
+                label,
+                values.into_iter().map(|value| self.resolve(value)).collect::<Result<_, _>>()?,
+            ),
+            decl => decl,
+        };
+
         match decl {
             Declare::ConstI64(
                 Node { expr: Token::Ident(ident), .. },
-                Node { expr: Token::Int(i), .. },
+                value @ Node { expr: Token::Int(_), .. },
             ) => {
-                self.st.add(ident, SymbolType::Integer(i));
+                let i = Int::try_from(&value)?.0;
+                self.align_data(4);
+
+                let addr = self.data.len();
+                self.data.extend_from_slice(&i.to_le_bytes());
+
+                self.declare(ident, SymbolType::Integer { value: i, addr })
             }
             Declare::ConstString(
                 Node { expr: Token::Ident(ident), .. },
                 Node { expr: Token::String(s), .. },
             ) => {
-                self.st.add(ident, SymbolType::String(s));
+                let addr = self.data.len();
+                self.data.extend_from_slice(&s);
+                self.data.push(0);
+
+                self.declare(ident, SymbolType::Data { addr, len: s.len() + 1 })
+            }
+            Declare::ConstAscii(
+                Node { expr: Token::Ident(ident), .. },
+                Node { expr: Token::String(s), .. },
+            ) => {
+                let addr = self.data.len();
+                self.data.extend_from_slice(&s);
+
+                self.declare(ident, SymbolType::Data { addr, len: s.len() })
+            }
+            Declare::ConstBytes(Node { expr: Token::Ident(ident), .. }, values) => {
+                let mut bytes = Vec::new();
+                for value in values {
+                    match &value.expr {
+                        Token::String(s) => bytes.extend_from_slice(s),
+                        Token::Int(i) if (-128..=255).contains(i) => bytes.push(*i as u8),
+                        Token::Int(_) => return Err(ParserError::ByteOutOfRange { token: value }),
+                        _ => return Err(ParserError::ArgumentInvalid { token: value }),
+                    }
+                }
+
+                let addr = self.data.len();
+                self.data.extend_from_slice(&bytes);
+
+                self.declare(ident, SymbolType::Data { addr, len: bytes.len() })
+            }
+            Declare::JumpTable(Node { expr: Token::Ident(ident), .. }, targets) => {
+                self.align_data(4);
+
+                let addr = self.data.len();
+                self.data.resize(addr + 4 * targets.len(), 0);
+                self.declare(ident, SymbolType::JumpTable { addr, len: targets.len() })?;
+
+                // Code labels are only known after the code segment.
+                self.jump_tables.push((addr, targets));
+
+                Ok(())
+            }
+            Declare::Equ(name, expr) => {
+                if self.st.contains(&name.expr) || self.constants.contains(&name.expr) {
+                    return Err(ParserError::LabelDuplicate(name.expr));
+                }
+
+                self.constants.define(name.expr, expr);
+
+                Ok(())
+            }
+            Declare::ConstI64(Node { expr: Token::Ident(_), .. }, value) |
+            Declare::ConstString(Node { expr: Token::Ident(_), .. }, value) |
+            Declare::ConstAscii(Node { expr: Token::Ident(_), .. }, value) => {
+                Err(ParserError::ArgumentInvalid { token: value })
+            }
+            Declare::ConstI64(label, _) |
+            Declare::ConstString(label, _) |
+            Declare::ConstAscii(label, _) |
+            Declare::ConstBytes(label, _) |
+            Declare::JumpTable(label, _) => {
+                Err(ParserError::ArgumentInvalid { token: label })
             }
-            _ => panic!("unexpected form: {:?}", decl)
         }
     }
 
-    fn process_code_segment(&mut self, code_segment: Vec<Node<Expression>>) {
-        let prepared = code_segment.into_iter().
-            enumerate().
-            map(|(idx, expr)| {
-                let start = expr.start;
-                let end = expr.end;
-
-                match expr.expr {
-                    Expression::Call(_, _) => (None, expr),
-                    Expression::Label(label, expr) => {
-                        (Some((label, SymbolType::Label(idx))), Node {
-                            start,
-                            end,
-                            expr: *expr,
-                        })
+    fn process_jump_tables(&mut self) {
+        for (addr, targets) in std::mem::take(&mut self.jump_tables) {
+            for (i, target) in targets.into_iter().enumerate() {
+                let offset = match &target.expr {
+                    Token::Ident(label) => self.st.get_offset(label)
+                        .ok_or(ParserError::LabelUnknown { label: target.clone() }),
+                    _ => Err(ParserError::ArgumentInvalid { token: target.clone() }),
+                };
+
+                match offset {
+                    Ok(offset) => {
+                        let at = addr + 4 * i;
+                        self.data[at..at + 4].copy_from_slice(&(offset as u32).to_le_bytes());
                     }
+                    Err(err) => self.error((target.start, target.end), err),
                 }
-            }).collect::<Vec<_>>();
+            }
+        }
+    }
+
+    /// Reports `.equ` definitions that cannot be evaluated, used or not.
+    fn process_constants(&mut self) {
+        let errors = self.constants.iter()
+            .filter_map(|(_, expr)| self.constants.eval(expr, &self.st).err().map(|err| ((expr.start, expr.end), err)))
+            .collect::<Vec<_>>();
+
+        for (span, err) in errors {
+            self.error(span, err);
+        }
+    }
 
-        for (sm, _) in &prepared {
-            if let Some((label, symbol)) = sm {
-                self.st.add(label.clone(), symbol.clone());
+    fn align_data(&mut self, align: usize) {
+        while !self.data.len().is_multiple_of(align) {
+            self.data.push(0);
+        }
+    }
+
+    fn process_code_segment(&mut self, code_segment: Vec<Node<Expression>>) {
+        let code_segment = self.expand_macros(code_segment);
+        let lines = self.expand_functions(code_segment);
+
+        let mut pc = 0;
+        for line in &lines {
+            match line {
+                Line::Label(label, notes) => {
+                    if let Err(err) = self.declare(label.expr.clone(), SymbolType::Label(pc)) {
+                        self.error_in((label.start, label.end), err, notes.clone());
+                    }
+                }
+                Line::Op(..) => pc += 1,
             }
         }
 
-        for (_, expr) in prepared {
-            if let Expression::Call(op, args) = expr.expr {
-                let instruction = self.process_op_expression(op, args);
+        for line in lines {
+            if let Line::Op(Node { start, end, expr: (op, args) }, notes) = line {
+                self.context = notes;
+
+                let args = args.into_iter().map(|arg| self.resolve(arg)).collect::<Result<Vec<_>, _>>();
 
-                self.instructions.push(instruction);
+                match args.and_then(|args| self.process_op_expression(op, args)) {
+                    Ok(instruction) => self.instructions.push(instruction),
+                    Err(err) => self.error((start, end), err),
+                }
             }
         }
+
+        self.context = Vec::new();
     }
 
     fn process_op_expression(&mut self, op: String, args: Vec<TokenNode>) -> Result<Instruction, ParserError> {
         match op.as_str() {
+            "igl" => Ok(Instruction::IGL),
             "ret" => Ok(Instruction::RET),
             "hlt" => Ok(Instruction::HLT),
             "load" => {
@@ -122,6 +418,26 @@ impl Parser {
 
                 Ok(instruction.0)
             }
+            "loadb" | "loadh" | "loadw" => {
+                let instruction: expr::LoadM<Instruction> = (op.as_str(), args).try_into()?;
+
+                Ok(instruction.0)
+            }
+            "storeb" | "storeh" | "storew" => {
+                let instruction: expr::StoreM<Instruction> = (op.as_str(), args).try_into()?;
+
+                Ok(instruction.0)
+            }
+            "push" | "pop" => {
+                let instruction: expr::Stack<Instruction> = (op.as_str(), args).try_into()?;
+
+                Ok(instruction.0)
+            }
+            "switch" => {
+                let instruction: expr::Switch<Instruction> = (args, &self.st).try_into()?;
+
+                Ok(instruction.0)
+            }
             "cloop" => {
                 let instruction: expr::CLoop<Instruction> = (args, &self.st).try_into()?;
 
@@ -132,12 +448,17 @@ impl Parser {
 
                 Ok(instruction.0)
             }
+            "syscall" => {
+                let instruction: expr::Syscall<Instruction> = args.try_into()?;
+
+                Ok(instruction.0)
+            }
             "inc" => {
                 let instruction: expr::Inc<Instruction> = (args, &self.st).try_into()?;
 
                 Ok(instruction.0)
             }
-            "jmp" | "jmpe" | "jmpne" => {
+            "jmp" | "jmpe" | "jmpne" | "jmpc" | "jmpo" => {
                 let instruction: expr::Jmp<Instruction> = (op.as_str(), args, &self.st).try_into()?;
 
                 Ok(instruction.0)
@@ -147,17 +468,47 @@ impl Parser {
 
                 Ok(instruction.0)
             }
-            "add" | "sub" | "mul" | "div" => {
+            "add" | "sub" | "mul" | "div" | "mod" | "divmod" | "divu" | "modu" | "addc" | "subb" => {
                 let instruction: expr::Math<Instruction> = (op.as_str(), args).try_into()?;
 
                 Ok(instruction.0)
             }
+            "and" | "or" | "xor" | "shl" | "shr" | "sar" => {
+                let instruction: expr::Bitwise<Instruction> = (op.as_str(), args).try_into()?;
+
+                Ok(instruction.0)
+            }
+            "not" => {
+                let instruction: expr::Not<Instruction> = args.try_into()?;
+
+                Ok(instruction.0)
+            }
             "eq" | "neq" | "gte" | "lte" | "lt" | "gt" => {
                 let instruction: expr::Cmp<Instruction> = (op.as_str(), args).try_into()?;
 
                 Ok(instruction.0)
             }
-            _ => panic!("unknown token: {:?}", op)
+            "loadf" => {
+                let instruction: expr::LoadF<Instruction> = args.try_into()?;
+
+                Ok(instruction.0)
+            }
+            "fadd" | "fsub" | "fmul" | "fdiv" => {
+                let instruction: expr::FMath<Instruction> = (op.as_str(), args).try_into()?;
+
+                Ok(instruction.0)
+            }
+            "feq" | "fneq" | "fgte" | "flte" | "flt" | "fgt" => {
+                let instruction: expr::FCmp<Instruction> = (op.as_str(), args).try_into()?;
+
+                Ok(instruction.0)
+            }
+            "itof" | "ftoi" => {
+                let instruction: expr::Convert<Instruction> = (op.as_str(), args).try_into()?;
+
+                Ok(instruction.0)
+            }
+            _ => Err(ParserError::OpUnknown(op))
         }
     }
 }
@@ -193,6 +544,297 @@ ret
     println!("{:?}", p.st);
 }
 
+#[test]
+fn test_memory_instructions() {
+    use crate::instruction::Width;
+
+    let code = "
+.data
+.code
+storew $1 [$0+4]
+loadb $2 [$0-1]
+loadh $3 [$0]
+hlt
+";
+
+    let program = Parser::new().process(code).unwrap();
+
+    assert_eq!(program.instructions, vec![
+        Instruction::STOREM { width: Width::Word, rs: 1, base: 0, offset: 4 },
+        Instruction::LOADM { width: Width::Byte, rd: 2, base: 0, offset: -1 },
+        Instruction::LOADM { width: Width::Half, rd: 3, base: 0, offset: 0 },
+        Instruction::HLT,
+    ]);
+}
+
+#[test]
+fn test_data_segment() {
+    let code = "
+.data
+greeting: .asciiz 'hi'
+answer: .integer #42
+.code
+load $0 @greeting
+load $1 @answer
+load $2 &answer
+load $3 &greeting
+hlt
+";
+
+    let program = Parser::new().process(code).unwrap();
+
+    assert_eq!(program.data, vec![b'h', b'i', 0, 0, 42, 0, 0, 0]);
+    assert_eq!(program.instructions, vec![
+        Instruction::LOAD { rd: 0, value: 0 },
+        Instruction::LOAD { rd: 1, value: 4 },
+        Instruction::LOAD { rd: 2, value: 4 },
+        Instruction::LOAD { rd: 3, value: 0 },
+        Instruction::HLT,
+    ]);
+}
+
+#[test]
+fn test_diagnostics() {
+    let code = "
+.data
+.code
+load $0 @missing
+start:
+frob $1
+jmp @nowhere
+start:
+add $0 $1
+hlt
+";
+
+    let diagnostics = Parser::new().process(code).unwrap_err();
+    let summary = diagnostics.iter()
+        .map(|d| (d.line, d.column, d.len, d.message.as_str()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(summary, vec![
+        (4, 9, 8, "unknown label `missing`"),
+        (6, 1, 7, "unknown instruction `frob`"),
+        (7, 5, 8, "unknown label `nowhere`"),
+        (8, 1, 5, "label `start` is already defined"),
+        (9, 1, 9, "expected 3 argument(s), got 2"),
+    ]);
+    assert_eq!("jmp @nowhere", diagnostics[2].snippet);
+
+    let diagnostics = Parser::new().process(".data\n.code\nload $0 #1 ,\n").unwrap_err();
+    assert_eq!((3, 12), (diagnostics[0].line, diagnostics[0].column));
+
+    let diagnostics = Parser::new().process(".data\n.code\nload $0 é\nhlt\n").unwrap_err();
+    assert_eq!((3, 9, 1), (diagnostics[0].line, diagnostics[0].column, diagnostics[0].len));
+}
+
+#[test]
+fn test_string_data() {
+    let code = r#"
+.data
+hello: .asciiz "Hi, you!\n"
+raw: .ascii 'ab'
+bytes: .bytes #1, #0xFF, #-1, "c"
+latin: .asciiz "caf\xE9"
+.code
+hlt
+"#;
+
+    let program = Parser::new().process(code).unwrap();
+
+    assert_eq!(program.data, b"Hi, you!\n\0ab\x01\xFF\xFFccaf\xE9\0");
+    assert_eq!(program.symbols.get_address("raw"), Some(10));
+    assert_eq!(program.symbols.get_address("bytes"), Some(12));
+
+    let diagnostics = Parser::new().process(".data\nb: .bytes #1, #256\n.code\nhlt\n").unwrap_err();
+    assert_eq!((2, 15, "integer 256 does not fit into a byte"), (diagnostics[0].line, diagnostics[0].column, diagnostics[0].message.as_str()));
+}
+
+#[test]
+fn test_numeric_literals() {
+    let code = "
+.data
+mask: .integer #0xFF_FF
+letter: .integer #'A'
+.code
+load $0 #0x1F
+load $1 #0b1010
+load $2 #0o17
+load $3 #1_000_000
+load $4 #-0x10
+load $5 #0xFFFFFFFF
+and $6 $0 #'a'
+hlt
+";
+
+    let program = Parser::new().process(code).unwrap();
+
+    assert_eq!(program.data, vec![0xFF, 0xFF, 0, 0, b'A', 0, 0, 0]);
+    assert_eq!(program.instructions[..7], [
+        Instruction::LOAD { rd: 0, value: 31 },
+        Instruction::LOAD { rd: 1, value: 10 },
+        Instruction::LOAD { rd: 2, value: 15 },
+        Instruction::LOAD { rd: 3, value: 1_000_000 },
+        Instruction::LOAD { rd: 4, value: -16 },
+        Instruction::LOAD { rd: 5, value: -1 },
+        Instruction::ANDI { rd: 6, rl: 0, value: 97 },
+    ]);
+
+    let diagnostics = Parser::new().process("
+.data
+big: .integer #0x1_0000_0000
+.code
+load $0 #-2147483649
+hlt
+").unwrap_err();
+    let summary = diagnostics.iter()
+        .map(|d| (d.line, d.column, d.len, d.message.as_str()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(summary, vec![
+        (3, 15, 14, "integer 4294967296 does not fit into 32 bits"),
+        (5, 9, 12, "integer -2147483649 does not fit into 32 bits"),
+    ]);
+
+    let diagnostics = Parser::new().process(".data\n.code\nload $0 #99999999999999999999\n").unwrap_err();
+    assert!(diagnostics[0].message.contains("integer literal"), "{}", diagnostics[0].message);
+
+    let program = Parser::new().process(".data\n.code\ncloop #3000000000\nsyscall #0xFFFFFFFF\nhlt\n").unwrap();
+    assert_eq!(program.instructions[..2], [
+        Instruction::CLOOP { count: 3_000_000_000 },
+        Instruction::SYSCALL { n: u32::MAX as usize },
+    ]);
+
+    let diagnostics = Parser::new().process(".data\n.code\ncloop #0x1_0000_0000\nhlt\n").unwrap_err();
+    assert_eq!("integer 4294967296 does not fit into 32 bits", diagnostics[0].message);
+
+    let diagnostics = Parser::new().process(".data\n.code\ncloop #-1\nsyscall #-2\nhlt\n").unwrap_err();
+    let summary = diagnostics.iter()
+        .map(|d| (d.line, d.column, d.len, d.message.as_str()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(summary, vec![
+        (3, 7, 3, "integer -1 must not be negative"),
+        (4, 9, 3, "integer -2 must not be negative"),
+    ]);
+}
+
+#[test]
+fn test_constants() {
+    let code = "
+.data
+.equ TOTAL SIZE * 4 + 1
+.equ SIZE 4
+.equ MASK #(1 << 8) - 1
+answer: .integer #(TOTAL * 2)
+.jumptable ops: @start, @end
+flags: .bytes #MASK, #(~0 & 0x7F)
+.code
+start:
+load $0 #(SIZE * 4 + 1)
+load $1 #-SIZE
+load $2 @ops+4
+load $3 #(end - start)
+and $4 $0 #MASK
+load $5 #(&answer + 4)
+end:
+hlt
+";
+
+    let program = Parser::new().process(code).unwrap();
+
+    assert_eq!(program.data[..4], [34, 0, 0, 0]);
+    assert_eq!(program.data[12..], [255, 127]);
+    assert_eq!(program.instructions[..6], [
+        Instruction::LOAD { rd: 0, value: 17 },
+        Instruction::LOAD { rd: 1, value: -4 },
+        Instruction::LOAD { rd: 2, value: 8 },
+        Instruction::LOAD { rd: 3, value: 6 },
+        Instruction::ANDI { rd: 4, rl: 0, value: 255 },
+        Instruction::LOAD { rd: 5, value: 4 },
+    ]);
+
+    let diagnostics = Parser::new().process("
+.data
+.equ A B + 1
+.equ B A
+.equ BIG 0x7FFFFFFFFFFFFFFF + 1
+.code
+load $0 #UNKNOWN
+load $1 #(1 / 0)
+load $2 #(1 << 40)
+load $3 #A
+hlt
+").unwrap_err();
+    let summary = diagnostics.iter()
+        .map(|d| (d.line, d.column, d.message.as_str()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(summary, vec![
+        (3, 8, "`B` is defined in terms of itself"),
+        (4, 8, "`A` is defined in terms of itself"),
+        (5, 10, "constant expression overflows"),
+        (7, 10, "unknown name `UNKNOWN`"),
+        (8, 11, "division by zero in constant expression"),
+        (9, 9, "integer 1099511627776 does not fit into 32 bits"),
+    ]);
+}
+
+#[test]
+fn test_functions() {
+    use crate::instruction::Width;
+
+    let code = "
+.data
+.code
+load $1 #3
+call @triple
+hlt
+.func triple(n)
+load $20 #3
+mul $0 $n $20
+jmpe @out
+ret
+out:
+ret
+.endfunc
+";
+
+    let program = Parser::new().process(code).unwrap();
+    let restore = Instruction::LOADBP { width: Width::Word, rd: 20, offset: -4 };
+
+    assert_eq!(program.symbols.get_offset("triple"), Some(3));
+    assert_eq!(program.symbols.get_offset("out"), Some(9));
+    assert_eq!(program.instructions[3..], [
+        Instruction::PUSH { rs: 20 },
+        Instruction::LOAD { rd: 20, value: 3 },
+        Instruction::MUL { rd: 0, rl: 1, rh: 20 },
+        Instruction::JMPE { dst: 9 },
+        restore,
+        Instruction::RET,
+        restore,
+        Instruction::RET,
+    ]);
+
+    let diagnostics = Parser::new().process("
+.data
+.code
+.endfunc
+.func f(a)
+add $0 $a $b
+.func g()
+ret
+").unwrap_err();
+    let messages = diagnostics.iter().map(|d| d.message.as_str()).collect::<Vec<_>>();
+
+    assert_eq!(messages, vec![
+        "`.endfunc` without `.func`",
+        "function `f` has no `.endfunc`",
+        "unknown register `$b`",
+        "`.func` cannot be nested",
+    ]);
+}
+
 // load $0 @label   0   0   -
 // load $1 #3       1   1   -
 // label1:          2   -   2
@@ -200,3 +842,95 @@ ret
 // load $0 #1       4   3   -
 // label2:          5   -   4
 // sub $2 $0 $1     6   4
+
+#[test]
+fn test_macros() {
+    let code = "
+.data
+.code
+.macro countdown r, n
+load \\r #\\n
+again\\@: sub \\r \\r $30
+neq \\r $29
+jmpe @again\\@
+.endm
+.macro both a, b
+countdown \\a #(\\b * 2)
+countdown \\a \\b
+.endm
+again2: load $30 #1
+load $29 #0
+start: both $1 #3
+hlt
+";
+
+    let program = Parser::new().process(code).unwrap();
+
+    assert_eq!(program.symbols.get_offset("start"), Some(2));
+    assert_eq!(program.symbols.get_offset("again2"), Some(0));
+    assert_eq!(program.symbols.get_offset("again__m2"), Some(3));
+    assert_eq!(program.symbols.get_offset("again__m3"), Some(7));
+    assert_eq!(program.instructions[2..], [
+        Instruction::LOAD { rd: 1, value: 6 },
+        Instruction::SUB { rd: 1, rl: 1, rh: 30 },
+        Instruction::NEQ { rl: 1, rh: 29 },
+        Instruction::JMPE { dst: 3 },
+        Instruction::LOAD { rd: 1, value: 3 },
+        Instruction::SUB { rd: 1, rl: 1, rh: 30 },
+        Instruction::NEQ { rl: 1, rh: 29 },
+        Instruction::JMPE { dst: 7 },
+        Instruction::HLT,
+    ]);
+
+    let source = crate::disassembler::disassemble(&program);
+    assert_eq!(Ok(program), Parser::new().process(&source));
+
+    let diagnostics = Parser::new().process("
+.data
+.code
+.macro set r, v
+load \\r #\\v
+.endm
+.macro forever
+top: forever
+.endm
+.macro bad
+load $0 #\\q
+.endm
+.macro tail
+end: hlt
+.endm
+.macro empty
+.endm
+set $1
+set $1 $2
+forever
+bad
+tail
+tail
+here: empty
+out\\@: hlt
+.endm
+").unwrap_err();
+    let summary = diagnostics.iter()
+        .map(|d| {
+            let notes = d.notes.iter().map(|n| (n.line, n.message.clone())).collect::<Vec<_>>();
+
+            (d.line, d.column, d.message.as_str(), notes)
+        })
+        .collect::<Vec<_>>();
+
+    let defined = |line, name| (line, format!("macro `{}` defined here", name));
+    let expanded = |line, name| (line, format!("in expansion of macro `{}`", name));
+    assert_eq!(summary, vec![
+        (8, 6, "macro `forever` expands to itself", vec![expanded(20, "forever"), defined(7, "forever")]),
+        (11, 10, "unknown macro parameter `\\q`", vec![expanded(21, "bad")]),
+        (14, 1, "label `end` is already defined", vec![expanded(23, "tail")]),
+        (18, 1, "expected 2 argument(s), got 1", vec![defined(4, "set")]),
+        (19, 8, "invalid argument Register(2)", vec![expanded(19, "set")]),
+        (24, 1, "label `here` has no instruction to name", vec![]),
+        (25, 1, "`\\@` can only be used inside a macro", vec![]),
+        (26, 1, "`.endm` without `.macro`", vec![]),
+    ]);
+    assert!(diagnostics[0].to_string().contains("note: in expansion of macro `forever`\n  --> 20:1"), "{}", diagnostics[0]);
+}
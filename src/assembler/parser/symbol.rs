@@ -1,18 +1,20 @@
 use std::collections::HashMap;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SymbolType {
     Label(usize),
-    Integer(i32),
-    String(String),
+    Integer { value: i32, addr: usize },
+    Data { addr: usize, len: usize },
+    /// `len` little-endian code offsets of one word each, starting at `addr`.
+    JumpTable { addr: usize, len: usize },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Symbol {
     pub stype: SymbolType,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct SymbolTable(HashMap<String, Symbol>);
 
 impl SymbolTable {
@@ -22,6 +24,24 @@ impl SymbolTable {
     pub fn add(&mut self, k: String, st: SymbolType) -> Option<Symbol> {
         self.0.insert(k, Symbol { stype: st })
     }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// Symbols ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &SymbolType)> {
+        let mut symbols = self.0.iter()
+            .map(|(k, symbol)| (k.as_str(), &symbol.stype))
+            .collect::<Vec<_>>();
+        symbols.sort_by_key(|(k, _)| *k);
+
+        symbols.into_iter()
+    }
+    pub fn contains(&self, k: &str) -> bool {
+        self.0.contains_key(k)
+    }
     pub fn get_offset(&self, k: &str) -> Option<usize> {
         let symbol = self.0.get(k)?;
 
@@ -34,7 +54,25 @@ impl SymbolTable {
         let symbol = self.0.get(k)?;
 
         match symbol.stype {
-            SymbolType::Integer(value) => Some(value),
+            SymbolType::Integer { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+    pub fn get_jump_table(&self, k: &str) -> Option<(usize, usize)> {
+        let symbol = self.0.get(k)?;
+
+        match symbol.stype {
+            SymbolType::JumpTable { addr, len } => Some((addr, len)),
+            _ => None,
+        }
+    }
+    pub fn get_address(&self, k: &str) -> Option<usize> {
+        let symbol = self.0.get(k)?;
+
+        match symbol.stype {
+            SymbolType::Integer { addr, .. } => Some(addr),
+            SymbolType::Data { addr, .. } => Some(addr),
+            SymbolType::JumpTable { addr, .. } => Some(addr),
             _ => None,
         }
     }
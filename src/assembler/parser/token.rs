@@ -14,7 +14,20 @@ impl TryFrom<&Node<Token>> for Register {
     fn try_from(value: &Node<Token>) -> Result<Self, Self::Error> {
         match &value.expr {
             Token::Register(reg) => Ok(Register(*reg)),
-            tok => Err(ParserError::ArgumentInvalid { token: tok.clone() })
+            _ => Err(ParserError::ArgumentInvalid { token: value.clone() })
+        }
+    }
+}
+
+pub struct FloatRegister(pub usize);
+
+impl TryFrom<&Node<Token>> for FloatRegister {
+    type Error = ParserError;
+
+    fn try_from(value: &Node<Token>) -> Result<Self, Self::Error> {
+        match &value.expr {
+            Token::FloatRegister(reg) => Ok(FloatRegister(*reg)),
+            _ => Err(ParserError::ArgumentInvalid { token: value.clone() })
         }
     }
 }
@@ -27,11 +40,13 @@ impl TryFrom<&Node<Token>> for Ident {
     fn try_from(value: &Node<Token>) -> Result<Self, Self::Error> {
         match &value.expr {
             Token::Ident(reg) => Ok(Ident(reg.clone())),
-            tok => Err(ParserError::ArgumentInvalid { token: tok.clone() })
+            _ => Err(ParserError::ArgumentInvalid { token: value.clone() })
         }
     }
 }
 
+/// 32-bit immediate. Literals above `i32::MAX` are accepted up to
+/// `u32::MAX` and taken as a bit pattern, so `#0xFFFFFFFF` is `-1`.
 pub struct Int(pub i32);
 
 impl TryFrom<&Node<Token>> for Int {
@@ -39,8 +54,58 @@ impl TryFrom<&Node<Token>> for Int {
 
     fn try_from(value: &Node<Token>) -> Result<Self, Self::Error> {
         match &value.expr {
-            Token::Int(reg) => Ok(Int(*reg)),
-            tok => Err(ParserError::ArgumentInvalid { token: tok.clone() })
+            Token::Int(i) if (i32::MIN as i64..=u32::MAX as i64).contains(i) => Ok(Int(*i as i32)),
+            Token::Int(_) => Err(ParserError::IntOutOfRange { token: value.clone() }),
+            _ => Err(ParserError::ArgumentInvalid { token: value.clone() })
+        }
+    }
+}
+
+/// Unsigned 32-bit immediate, for counts and syscall numbers. Negative
+/// literals are rejected rather than wrapped.
+pub struct UInt(pub u32);
+
+impl TryFrom<&Node<Token>> for UInt {
+    type Error = ParserError;
+
+    fn try_from(value: &Node<Token>) -> Result<Self, Self::Error> {
+        match &value.expr {
+            Token::Int(i) if *i < 0 => Err(ParserError::IntNegative { token: value.clone() }),
+            Token::Int(i) => u32::try_from(*i)
+                .map(UInt)
+                .map_err(|_| ParserError::IntOutOfRange { token: value.clone() }),
+            _ => Err(ParserError::ArgumentInvalid { token: value.clone() })
+        }
+    }
+}
+
+/// Float immediate, `#3` is accepted as well as `#3.0`.
+pub struct Float(pub f32);
+
+impl TryFrom<&Node<Token>> for Float {
+    type Error = ParserError;
+
+    fn try_from(value: &Node<Token>) -> Result<Self, Self::Error> {
+        match &value.expr {
+            Token::Float(f) => Ok(Float(*f)),
+            Token::Int(i) => Ok(Float(*i as f32)),
+            _ => Err(ParserError::ArgumentInvalid { token: value.clone() })
         }
     }
-}
\ No newline at end of file
+}
+
+pub struct Address {
+    pub base: usize,
+    pub offset: i32,
+}
+
+impl TryFrom<&Node<Token>> for Address {
+    type Error = ParserError;
+
+    fn try_from(value: &Node<Token>) -> Result<Self, Self::Error> {
+        match &value.expr {
+            Token::Address { base, offset } => Ok(Address { base: *base, offset: *offset }),
+            _ => Err(ParserError::ArgumentInvalid { token: value.clone() })
+        }
+    }
+}
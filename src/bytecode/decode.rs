@@ -0,0 +1,226 @@
+use crate::{
+    assembler::{SymbolTable, SymbolType},
+    instruction::{Instruction, Width},
+    program::Program,
+};
+
+use super::{
+    opcode,
+    symbol_kind,
+    DecodeError,
+    MAGIC,
+    VERSION,
+};
+
+struct Reader<'a> {
+    pos: usize,
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.pos.checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(DecodeError::UnexpectedEof { offset: self.pos })?;
+
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        let b = self.take(2)?;
+
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<usize, DecodeError> {
+        let b = self.take(4)?;
+
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    }
+
+    fn i32(&mut self) -> Result<i32, DecodeError> {
+        let b = self.take(4)?;
+
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f32(&mut self) -> Result<f32, DecodeError> {
+        let b = self.take(4)?;
+
+        Ok(f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn reg(&mut self) -> Result<usize, DecodeError> {
+        Ok(self.u8()? as usize)
+    }
+
+    fn width(&mut self) -> Result<Width, DecodeError> {
+        let offset = self.pos;
+
+        match self.u8()? {
+            0 => Ok(Width::Byte),
+            1 => Ok(Width::Half),
+            2 => Ok(Width::Word),
+            width => Err(DecodeError::InvalidWidth { offset, width }),
+        }
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, DecodeError> {
+        let offset = self.pos;
+
+        Ok(match self.u8()? {
+            opcode::IGL => Instruction::IGL,
+            opcode::HLT => Instruction::HLT,
+            opcode::RET => Instruction::RET,
+            opcode::JMP => Instruction::JMP { dst: self.u32()? },
+            opcode::JMPE => Instruction::JMPE { dst: self.u32()? },
+            opcode::JMPNE => Instruction::JMPNE { dst: self.u32()? },
+            opcode::JMPC => Instruction::JMPC { dst: self.u32()? },
+            opcode::JMPO => Instruction::JMPO { dst: self.u32()? },
+            opcode::CALL => Instruction::CALL { dst: self.u32()? },
+            opcode::JMPR => Instruction::JMPR { r: self.reg()? },
+            opcode::CALLR => Instruction::CALLR { r: self.reg()? },
+            opcode::SWITCH => Instruction::SWITCH {
+                r: self.reg()?,
+                table: self.u32()?,
+                len: self.u32()?,
+                default: self.u32()?,
+            },
+            opcode::CLOOP => Instruction::CLOOP { count: self.u32()? },
+            opcode::CLOOPR => Instruction::CLOOPR { r: self.reg()? },
+            opcode::LOOP => Instruction::LOOP { dst: self.u32()? },
+            opcode::SYSCALL => Instruction::SYSCALL { n: self.u32()? },
+            opcode::INC => Instruction::INC { r: self.reg()? },
+            opcode::LOAD => Instruction::LOAD { rd: self.reg()?, value: self.i32()? },
+            opcode::LOADM => {
+                let (rd, base) = (self.reg()?, self.reg()?);
+
+                Instruction::LOADM { rd, base, width: self.width()?, offset: self.i32()? }
+            }
+            opcode::STOREM => {
+                let (rs, base) = (self.reg()?, self.reg()?);
+
+                Instruction::STOREM { rs, base, width: self.width()?, offset: self.i32()? }
+            }
+            opcode::LOADBP => {
+                let rd = self.reg()?;
+
+                Instruction::LOADBP { rd, width: self.width()?, offset: self.i32()? }
+            }
+            opcode::STOREBP => {
+                let rs = self.reg()?;
+
+                Instruction::STOREBP { rs, width: self.width()?, offset: self.i32()? }
+            }
+            opcode::PUSH => Instruction::PUSH { rs: self.reg()? },
+            opcode::POP => Instruction::POP { rd: self.reg()? },
+            opcode::ADD => Instruction::ADD { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::SUB => Instruction::SUB { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::MUL => Instruction::MUL { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::DIV => Instruction::DIV { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::MOD => Instruction::MOD { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::DIVMOD => Instruction::DIVMOD {
+                rq: self.reg()?,
+                rr: self.reg()?,
+                rl: self.reg()?,
+                rh: self.reg()?,
+            },
+            opcode::DIVU => Instruction::DIVU { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::MODU => Instruction::MODU { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::ADDC => Instruction::ADDC { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::SUBB => Instruction::SUBB { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::AND => Instruction::AND { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::OR => Instruction::OR { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::XOR => Instruction::XOR { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::SHL => Instruction::SHL { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::SHR => Instruction::SHR { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::SAR => Instruction::SAR { rd: self.reg()?, rl: self.reg()?, rh: self.reg()? },
+            opcode::NOT => Instruction::NOT { rd: self.reg()?, rs: self.reg()? },
+            opcode::ANDI => Instruction::ANDI { rd: self.reg()?, rl: self.reg()?, value: self.i32()? },
+            opcode::ORI => Instruction::ORI { rd: self.reg()?, rl: self.reg()?, value: self.i32()? },
+            opcode::XORI => Instruction::XORI { rd: self.reg()?, rl: self.reg()?, value: self.i32()? },
+            opcode::SHLI => Instruction::SHLI { rd: self.reg()?, rl: self.reg()?, value: self.i32()? },
+            opcode::SHRI => Instruction::SHRI { rd: self.reg()?, rl: self.reg()?, value: self.i32()? },
+            opcode::SARI => Instruction::SARI { rd: self.reg()?, rl: self.reg()?, value: self.i32()? },
+            opcode::EQ => Instruction::EQ { rl: self.reg()?, rh: self.reg()? },
+            opcode::NEQ => Instruction::NEQ { rl: self.reg()?, rh: self.reg()? },
+            opcode::GTE => Instruction::GTE { rl: self.reg()?, rh: self.reg()? },
+            opcode::LTE => Instruction::LTE { rl: self.reg()?, rh: self.reg()? },
+            opcode::LT => Instruction::LT { rl: self.reg()?, rh: self.reg()? },
+            opcode::GT => Instruction::GT { rl: self.reg()?, rh: self.reg()? },
+            opcode::LOADF => Instruction::LOADF { fd: self.reg()?, value: self.f32()? },
+            opcode::FADD => Instruction::FADD { fd: self.reg()?, fl: self.reg()?, fh: self.reg()? },
+            opcode::FSUB => Instruction::FSUB { fd: self.reg()?, fl: self.reg()?, fh: self.reg()? },
+            opcode::FMUL => Instruction::FMUL { fd: self.reg()?, fl: self.reg()?, fh: self.reg()? },
+            opcode::FDIV => Instruction::FDIV { fd: self.reg()?, fl: self.reg()?, fh: self.reg()? },
+            opcode::FEQ => Instruction::FEQ { fl: self.reg()?, fh: self.reg()? },
+            opcode::FNEQ => Instruction::FNEQ { fl: self.reg()?, fh: self.reg()? },
+            opcode::FGTE => Instruction::FGTE { fl: self.reg()?, fh: self.reg()? },
+            opcode::FLTE => Instruction::FLTE { fl: self.reg()?, fh: self.reg()? },
+            opcode::FLT => Instruction::FLT { fl: self.reg()?, fh: self.reg()? },
+            opcode::FGT => Instruction::FGT { fl: self.reg()?, fh: self.reg()? },
+            opcode::ITOF => Instruction::ITOF { fd: self.reg()?, rs: self.reg()? },
+            opcode::FTOI => Instruction::FTOI { rd: self.reg()?, fs: self.reg()? },
+            opcode => return Err(DecodeError::UnknownOpcode { offset, opcode }),
+        })
+    }
+
+    fn symbol(&mut self) -> Result<(String, SymbolType), DecodeError> {
+        let len = self.u16()? as usize;
+        let offset = self.pos;
+        let name = std::str::from_utf8(self.take(len)?)
+            .map_err(|_| DecodeError::InvalidSymbolName { offset })?
+            .to_string();
+
+        let offset = self.pos;
+        let stype = match self.u8()? {
+            symbol_kind::LABEL => SymbolType::Label(self.u32()?),
+            symbol_kind::INTEGER => SymbolType::Integer { value: self.i32()?, addr: self.u32()? },
+            symbol_kind::DATA => SymbolType::Data { addr: self.u32()?, len: self.u32()? },
+            symbol_kind::JUMP_TABLE => SymbolType::JumpTable { addr: self.u32()?, len: self.u32()? },
+            kind => return Err(DecodeError::InvalidSymbolKind { offset, kind }),
+        };
+
+        Ok((name, stype))
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Program, DecodeError> {
+    let mut r = Reader { pos: 0, bytes };
+
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err(DecodeError::BadMagic);
+    }
+
+    let version = r.u16()?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    let mut instructions = Vec::new();
+    for _ in 0..r.u32()? {
+        instructions.push(r.instruction()?);
+    }
+
+    let len = r.u32()?;
+    let data = r.take(len)?.to_vec();
+
+    let mut symbols = SymbolTable::new();
+    for _ in 0..r.u32()? {
+        let (name, stype) = r.symbol()?;
+        symbols.add(name, stype);
+    }
+
+    if r.pos != bytes.len() {
+        return Err(DecodeError::TrailingBytes { offset: r.pos });
+    }
+
+    Ok(Program { instructions, data, symbols })
+}
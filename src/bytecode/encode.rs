@@ -0,0 +1,267 @@
+use std::convert::TryFrom;
+
+use crate::{
+    assembler::SymbolType,
+    instruction::{Instruction, Width},
+    program::Program,
+};
+
+use super::{
+    opcode,
+    symbol_kind,
+    EncodeError,
+    MAGIC,
+    VERSION,
+};
+
+struct Writer {
+    pc: usize,
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: usize) -> Result<(), EncodeError> {
+        let value = u32::try_from(value).map_err(|_| EncodeError::ValueOutOfRange { value })?;
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+
+        Ok(())
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn reg(&mut self, r: usize) -> Result<(), EncodeError> {
+        let r = u8::try_from(r).map_err(|_| EncodeError::RegisterOutOfRange { pc: self.pc, r })?;
+        self.u8(r);
+
+        Ok(())
+    }
+
+    fn width(&mut self, width: Width) {
+        self.u8(match width {
+            Width::Byte => 0,
+            Width::Half => 1,
+            Width::Word => 2,
+        });
+    }
+
+    fn op(&mut self, op: u8, regs: &[usize]) -> Result<(), EncodeError> {
+        self.u8(op);
+        for r in regs {
+            self.reg(*r)?;
+        }
+
+        Ok(())
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) -> Result<(), EncodeError> {
+        match *instruction {
+            Instruction::IGL => self.op(opcode::IGL, &[])?,
+            Instruction::HLT => self.op(opcode::HLT, &[])?,
+            Instruction::RET => self.op(opcode::RET, &[])?,
+            Instruction::JMP { dst } => {
+                self.op(opcode::JMP, &[])?;
+                self.u32(dst)?;
+            }
+            Instruction::JMPE { dst } => {
+                self.op(opcode::JMPE, &[])?;
+                self.u32(dst)?;
+            }
+            Instruction::JMPNE { dst } => {
+                self.op(opcode::JMPNE, &[])?;
+                self.u32(dst)?;
+            }
+            Instruction::JMPC { dst } => {
+                self.op(opcode::JMPC, &[])?;
+                self.u32(dst)?;
+            }
+            Instruction::JMPO { dst } => {
+                self.op(opcode::JMPO, &[])?;
+                self.u32(dst)?;
+            }
+            Instruction::CALL { dst } => {
+                self.op(opcode::CALL, &[])?;
+                self.u32(dst)?;
+            }
+            Instruction::JMPR { r } => self.op(opcode::JMPR, &[r])?,
+            Instruction::CALLR { r } => self.op(opcode::CALLR, &[r])?,
+            Instruction::SWITCH { r, table, len, default } => {
+                self.op(opcode::SWITCH, &[r])?;
+                self.u32(table)?;
+                self.u32(len)?;
+                self.u32(default)?;
+            }
+            Instruction::CLOOP { count } => {
+                self.op(opcode::CLOOP, &[])?;
+                self.u32(count)?;
+            }
+            Instruction::CLOOPR { r } => self.op(opcode::CLOOPR, &[r])?,
+            Instruction::LOOP { dst } => {
+                self.op(opcode::LOOP, &[])?;
+                self.u32(dst)?;
+            }
+            Instruction::SYSCALL { n } => {
+                self.op(opcode::SYSCALL, &[])?;
+                self.u32(n)?;
+            }
+            Instruction::INC { r } => self.op(opcode::INC, &[r])?,
+            Instruction::LOAD { rd, value } => {
+                self.op(opcode::LOAD, &[rd])?;
+                self.i32(value);
+            }
+            Instruction::LOADM { width, rd, base, offset } => {
+                self.op(opcode::LOADM, &[rd, base])?;
+                self.width(width);
+                self.i32(offset);
+            }
+            Instruction::STOREM { width, rs, base, offset } => {
+                self.op(opcode::STOREM, &[rs, base])?;
+                self.width(width);
+                self.i32(offset);
+            }
+            Instruction::LOADBP { width, rd, offset } => {
+                self.op(opcode::LOADBP, &[rd])?;
+                self.width(width);
+                self.i32(offset);
+            }
+            Instruction::STOREBP { width, rs, offset } => {
+                self.op(opcode::STOREBP, &[rs])?;
+                self.width(width);
+                self.i32(offset);
+            }
+            Instruction::PUSH { rs } => self.op(opcode::PUSH, &[rs])?,
+            Instruction::POP { rd } => self.op(opcode::POP, &[rd])?,
+            Instruction::ADD { rd, rl, rh } => self.op(opcode::ADD, &[rd, rl, rh])?,
+            Instruction::SUB { rd, rl, rh } => self.op(opcode::SUB, &[rd, rl, rh])?,
+            Instruction::MUL { rd, rl, rh } => self.op(opcode::MUL, &[rd, rl, rh])?,
+            Instruction::DIV { rd, rl, rh } => self.op(opcode::DIV, &[rd, rl, rh])?,
+            Instruction::MOD { rd, rl, rh } => self.op(opcode::MOD, &[rd, rl, rh])?,
+            Instruction::DIVMOD { rq, rr, rl, rh } => self.op(opcode::DIVMOD, &[rq, rr, rl, rh])?,
+            Instruction::DIVU { rd, rl, rh } => self.op(opcode::DIVU, &[rd, rl, rh])?,
+            Instruction::MODU { rd, rl, rh } => self.op(opcode::MODU, &[rd, rl, rh])?,
+            Instruction::ADDC { rd, rl, rh } => self.op(opcode::ADDC, &[rd, rl, rh])?,
+            Instruction::SUBB { rd, rl, rh } => self.op(opcode::SUBB, &[rd, rl, rh])?,
+            Instruction::AND { rd, rl, rh } => self.op(opcode::AND, &[rd, rl, rh])?,
+            Instruction::OR { rd, rl, rh } => self.op(opcode::OR, &[rd, rl, rh])?,
+            Instruction::XOR { rd, rl, rh } => self.op(opcode::XOR, &[rd, rl, rh])?,
+            Instruction::SHL { rd, rl, rh } => self.op(opcode::SHL, &[rd, rl, rh])?,
+            Instruction::SHR { rd, rl, rh } => self.op(opcode::SHR, &[rd, rl, rh])?,
+            Instruction::SAR { rd, rl, rh } => self.op(opcode::SAR, &[rd, rl, rh])?,
+            Instruction::NOT { rd, rs } => self.op(opcode::NOT, &[rd, rs])?,
+            Instruction::ANDI { rd, rl, value } => {
+                self.op(opcode::ANDI, &[rd, rl])?;
+                self.i32(value);
+            }
+            Instruction::ORI { rd, rl, value } => {
+                self.op(opcode::ORI, &[rd, rl])?;
+                self.i32(value);
+            }
+            Instruction::XORI { rd, rl, value } => {
+                self.op(opcode::XORI, &[rd, rl])?;
+                self.i32(value);
+            }
+            Instruction::SHLI { rd, rl, value } => {
+                self.op(opcode::SHLI, &[rd, rl])?;
+                self.i32(value);
+            }
+            Instruction::SHRI { rd, rl, value } => {
+                self.op(opcode::SHRI, &[rd, rl])?;
+                self.i32(value);
+            }
+            Instruction::SARI { rd, rl, value } => {
+                self.op(opcode::SARI, &[rd, rl])?;
+                self.i32(value);
+            }
+            Instruction::EQ { rl, rh } => self.op(opcode::EQ, &[rl, rh])?,
+            Instruction::NEQ { rl, rh } => self.op(opcode::NEQ, &[rl, rh])?,
+            Instruction::GTE { rl, rh } => self.op(opcode::GTE, &[rl, rh])?,
+            Instruction::LTE { rl, rh } => self.op(opcode::LTE, &[rl, rh])?,
+            Instruction::LT { rl, rh } => self.op(opcode::LT, &[rl, rh])?,
+            Instruction::GT { rl, rh } => self.op(opcode::GT, &[rl, rh])?,
+            Instruction::LOADF { fd, value } => {
+                self.op(opcode::LOADF, &[fd])?;
+                self.f32(value);
+            }
+            Instruction::FADD { fd, fl, fh } => self.op(opcode::FADD, &[fd, fl, fh])?,
+            Instruction::FSUB { fd, fl, fh } => self.op(opcode::FSUB, &[fd, fl, fh])?,
+            Instruction::FMUL { fd, fl, fh } => self.op(opcode::FMUL, &[fd, fl, fh])?,
+            Instruction::FDIV { fd, fl, fh } => self.op(opcode::FDIV, &[fd, fl, fh])?,
+            Instruction::FEQ { fl, fh } => self.op(opcode::FEQ, &[fl, fh])?,
+            Instruction::FNEQ { fl, fh } => self.op(opcode::FNEQ, &[fl, fh])?,
+            Instruction::FGTE { fl, fh } => self.op(opcode::FGTE, &[fl, fh])?,
+            Instruction::FLTE { fl, fh } => self.op(opcode::FLTE, &[fl, fh])?,
+            Instruction::FLT { fl, fh } => self.op(opcode::FLT, &[fl, fh])?,
+            Instruction::FGT { fl, fh } => self.op(opcode::FGT, &[fl, fh])?,
+            Instruction::ITOF { fd, rs } => self.op(opcode::ITOF, &[fd, rs])?,
+            Instruction::FTOI { rd, fs } => self.op(opcode::FTOI, &[rd, fs])?,
+        }
+
+        Ok(())
+    }
+
+    fn symbol(&mut self, name: &str, stype: &SymbolType) -> Result<(), EncodeError> {
+        let len = u16::try_from(name.len()).map_err(|_| EncodeError::NameTooLong { name: name.to_string() })?;
+        self.u16(len);
+        self.bytes.extend_from_slice(name.as_bytes());
+
+        match *stype {
+            SymbolType::Label(offset) => {
+                self.u8(symbol_kind::LABEL);
+                self.u32(offset)?;
+            }
+            SymbolType::Integer { value, addr } => {
+                self.u8(symbol_kind::INTEGER);
+                self.i32(value);
+                self.u32(addr)?;
+            }
+            SymbolType::Data { addr, len } => {
+                self.u8(symbol_kind::DATA);
+                self.u32(addr)?;
+                self.u32(len)?;
+            }
+            SymbolType::JumpTable { addr, len } => {
+                self.u8(symbol_kind::JUMP_TABLE);
+                self.u32(addr)?;
+                self.u32(len)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn encode(program: &Program) -> Result<Vec<u8>, EncodeError> {
+    let mut w = Writer { pc: 0, bytes: Vec::new() };
+
+    w.bytes.extend_from_slice(MAGIC);
+    w.u16(VERSION);
+
+    w.u32(program.instructions.len())?;
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        w.pc = pc;
+        w.instruction(instruction)?;
+    }
+
+    w.u32(program.data.len())?;
+    w.bytes.extend_from_slice(&program.data);
+
+    w.u32(program.symbols.len())?;
+    for (name, stype) in program.symbols.iter() {
+        w.symbol(name, stype)?;
+    }
+
+    Ok(w.bytes)
+}
@@ -0,0 +1,304 @@
+//! On-disk `.svm` format.
+//!
+//! ```text
+//! magic    b"SVM\0"
+//! version  u16
+//! code     u32 count, then per instruction: u8 opcode + operands
+//! data     u32 length, then the raw memory image
+//! symbols  u32 count, then per symbol: u16 name length, name, u8 kind + values
+//! ```
+//!
+//! All integers are little-endian. Registers are encoded as `u8`, code
+//! offsets, counts and addresses as `u32`, float immediates as the bits
+//! of an IEEE 754 `f32`.
+
+mod decode;
+mod encode;
+
+use std::{
+    fmt,
+    fs,
+    io,
+    path::Path,
+};
+
+use crate::{program::Program, verify::{verify, VerifyError}};
+
+pub use decode::decode;
+pub use encode::encode;
+
+pub const MAGIC: &[u8; 4] = b"SVM\0";
+pub const VERSION: u16 = 1;
+
+mod opcode {
+    pub const IGL: u8 = 0x00;
+    pub const HLT: u8 = 0x01;
+    pub const RET: u8 = 0x02;
+    pub const JMP: u8 = 0x03;
+    pub const JMPE: u8 = 0x04;
+    pub const JMPNE: u8 = 0x05;
+    pub const CALL: u8 = 0x06;
+    pub const CLOOP: u8 = 0x07;
+    pub const LOOP: u8 = 0x08;
+    pub const INC: u8 = 0x09;
+    pub const LOAD: u8 = 0x0A;
+    pub const LOADM: u8 = 0x0B;
+    pub const STOREM: u8 = 0x0C;
+    pub const LOADBP: u8 = 0x0D;
+    pub const STOREBP: u8 = 0x0E;
+    pub const PUSH: u8 = 0x31;
+    pub const POP: u8 = 0x32;
+    pub const ADD: u8 = 0x10;
+    pub const SUB: u8 = 0x11;
+    pub const MUL: u8 = 0x12;
+    pub const DIV: u8 = 0x13;
+    pub const ADDC: u8 = 0x14;
+    pub const SUBB: u8 = 0x15;
+    pub const MOD: u8 = 0x16;
+    pub const DIVMOD: u8 = 0x17;
+    pub const DIVU: u8 = 0x18;
+    pub const MODU: u8 = 0x19;
+    pub const JMPC: u8 = 0x26;
+    pub const JMPO: u8 = 0x27;
+    pub const JMPR: u8 = 0x28;
+    pub const CALLR: u8 = 0x29;
+    pub const SWITCH: u8 = 0x2A;
+    pub const CLOOPR: u8 = 0x2B;
+    pub const EQ: u8 = 0x20;
+    pub const NEQ: u8 = 0x21;
+    pub const GTE: u8 = 0x22;
+    pub const LTE: u8 = 0x23;
+    pub const LT: u8 = 0x24;
+    pub const GT: u8 = 0x25;
+    pub const SYSCALL: u8 = 0x30;
+    pub const LOADF: u8 = 0x40;
+    pub const FADD: u8 = 0x41;
+    pub const FSUB: u8 = 0x42;
+    pub const FMUL: u8 = 0x43;
+    pub const FDIV: u8 = 0x44;
+    pub const FEQ: u8 = 0x48;
+    pub const FNEQ: u8 = 0x49;
+    pub const FGTE: u8 = 0x4A;
+    pub const FLTE: u8 = 0x4B;
+    pub const FLT: u8 = 0x4C;
+    pub const FGT: u8 = 0x4D;
+    pub const ITOF: u8 = 0x4E;
+    pub const FTOI: u8 = 0x4F;
+    pub const AND: u8 = 0x50;
+    pub const OR: u8 = 0x51;
+    pub const XOR: u8 = 0x52;
+    pub const SHL: u8 = 0x53;
+    pub const SHR: u8 = 0x54;
+    pub const SAR: u8 = 0x55;
+    pub const NOT: u8 = 0x56;
+    pub const ANDI: u8 = 0x58;
+    pub const ORI: u8 = 0x59;
+    pub const XORI: u8 = 0x5A;
+    pub const SHLI: u8 = 0x5B;
+    pub const SHRI: u8 = 0x5C;
+    pub const SARI: u8 = 0x5D;
+}
+
+mod symbol_kind {
+    pub const LABEL: u8 = 0;
+    pub const INTEGER: u8 = 1;
+    pub const DATA: u8 = 2;
+    pub const JUMP_TABLE: u8 = 3;
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum EncodeError {
+    RegisterOutOfRange { pc: usize, r: usize },
+    ValueOutOfRange { value: usize },
+    NameTooLong { name: String },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnexpectedEof { offset: usize },
+    UnknownOpcode { offset: usize, opcode: u8 },
+    InvalidWidth { offset: usize, width: u8 },
+    InvalidSymbolKind { offset: usize, kind: u8 },
+    InvalidSymbolName { offset: usize },
+    TrailingBytes { offset: usize },
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Decode(DecodeError),
+    Verify(Vec<VerifyError>),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::RegisterOutOfRange { pc, r } => write!(f, "register ${} at pc {} cannot be encoded", r, pc),
+            EncodeError::ValueOutOfRange { value } => write!(f, "value {} does not fit into u32", value),
+            EncodeError::NameTooLong { name } => write!(f, "symbol name {:?} is too long", name),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "not a stupid_vm bytecode file"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported bytecode version {}", v),
+            DecodeError::UnexpectedEof { offset } => write!(f, "unexpected end of file at byte {}", offset),
+            DecodeError::UnknownOpcode { offset, opcode } => {
+                write!(f, "unknown opcode {:#04x} at byte {}", opcode, offset)
+            }
+            DecodeError::InvalidWidth { offset, width } => write!(f, "invalid width {} at byte {}", width, offset),
+            DecodeError::InvalidSymbolKind { offset, kind } => {
+                write!(f, "invalid symbol kind {} at byte {}", kind, offset)
+            }
+            DecodeError::InvalidSymbolName { offset } => write!(f, "symbol name at byte {} is not utf-8", offset),
+            DecodeError::TrailingBytes { offset } => write!(f, "trailing bytes after byte {}", offset),
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{}", err),
+            LoadError::Decode(err) => write!(f, "{}", err),
+            LoadError::Verify(errors) => {
+                write!(f, "program failed verification:")?;
+                for err in errors {
+                    write!(f, "\n    {}", err)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+impl std::error::Error for DecodeError {}
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<DecodeError> for LoadError {
+    fn from(err: DecodeError) -> Self {
+        LoadError::Decode(err)
+    }
+}
+
+pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Program, LoadError> {
+    let bytes = fs::read(path)?;
+    let program = decode(&bytes)?;
+    verify(&program).map_err(LoadError::Verify)?;
+
+    Ok(program)
+}
+
+pub fn save_file<P: AsRef<Path>>(path: P, program: &Program) -> io::Result<()> {
+    let bytes = encode(program).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    fs::write(path, bytes)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{assembler::Parser, instruction::Instruction};
+
+    const CODE: &str = "
+.data
+greeting: .asciiz 'hi'
+answer: .integer #42
+.jumptable cases: @again, @sub
+.code
+load $0 @greeting
+load $1 @answer
+storew $1 [$0+4]
+loadb $2 [$0-1]
+cloop #3
+cloop $2
+again:
+inc $2
+loop @again
+call @sub
+hlt
+sub:
+div $3 $2 $1
+divmod $3 $4 $2 $1
+modu $4 $3 $1
+gte $3 $1
+jmpne @sub
+switch $3 @cases @sub
+ret
+";
+
+    #[test]
+    fn roundtrip() {
+        let program = Parser::new().process(CODE).unwrap();
+        let bytes = encode(&program).unwrap();
+
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(Ok(program), decode(&bytes));
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let bytes = encode(&Parser::new().process(CODE).unwrap()).unwrap();
+
+        assert_eq!(Err(DecodeError::BadMagic), decode(b"ELF\0\x01\x00"));
+        assert_eq!(Err(DecodeError::UnexpectedEof { offset: 0 }), decode(b""));
+
+        let mut version = bytes.clone();
+        version[4] = 99;
+        assert_eq!(Err(DecodeError::UnsupportedVersion(99)), decode(&version));
+
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len]).is_err(), "truncated at {} decoded", len);
+        }
+
+        let mut opcode = bytes.clone();
+        opcode[10] = 0xFF;
+        assert_eq!(Err(DecodeError::UnknownOpcode { offset: 10, opcode: 0xFF }), decode(&opcode));
+
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(matches!(decode(&trailing), Err(DecodeError::TrailingBytes { .. })));
+    }
+
+    #[test]
+    fn encode_rejects_wide_registers() {
+        let program = Program {
+            instructions: vec![Instruction::INC { r: 300 }],
+            ..Program::default()
+        };
+
+        assert_eq!(Err(EncodeError::RegisterOutOfRange { pc: 0, r: 300 }), encode(&program));
+    }
+
+    #[test]
+    fn load_verifies() {
+        let path = std::env::temp_dir().join(format!("stupid_vm_load_{}.svm", std::process::id()));
+        let program = Program {
+            instructions: vec![Instruction::INC { r: 40 }, Instruction::HLT],
+            ..Program::default()
+        };
+
+        save_file(&path, &program).unwrap();
+        let result = load_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(LoadError::Verify(errors)) => assert_eq!(vec![VerifyError::RegisterOutOfRange { pc: 0, r: 40 }], errors),
+            other => panic!("expected a verify error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use stupid_vm::{
+    assembler::{convention::check_convention, Parser},
+    bytecode,
+    debugger::Debugger,
+    disassembler,
+    program::Program,
+    vm::{ExitStatus, FaultReason, Host, OverflowMode, VM},
+};
+
+pub const USAGE: &str = "usage:
+    stupid_vm asm <in.s> [-o <out.svm>]
+    stupid_vm run <file.s|file.svm> [--trace] [--max-steps <n>] [--overflow wrap|trap|saturate]
+    stupid_vm disasm <file.svm>
+    stupid_vm debug <file.s|file.svm>";
+
+/// Exit codes of the driver itself. A halted guest exits with its own
+/// status when it is in `0..=GUEST_MAX`, below every driver code. Any
+/// other status is printed to stderr and exits with `GUEST_OUT_OF_RANGE`.
+pub mod exit {
+    pub const GUEST_MAX: i32 = 62;
+    pub const GUEST_OUT_OF_RANGE: i32 = 63;
+    pub const USAGE: i32 = 64;
+    pub const INPUT: i32 = 66;
+    pub const FAULT: i32 = 70;
+    pub const STEP_LIMIT: i32 = 124;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Asm { input: PathBuf, output: PathBuf },
+    Run { input: PathBuf, trace: bool, max_steps: Option<u64>, overflow: OverflowMode },
+    Disasm { input: PathBuf },
+    Debug { input: PathBuf },
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter();
+
+    let command = args.next().ok_or("missing command")?;
+    if !matches!(command.as_str(), "asm" | "run" | "disasm" | "debug") {
+        return Err(format!("unknown command: {}", command));
+    }
+
+    let mut input = None;
+    let mut output = None;
+    let mut trace = false;
+    let mut max_steps = None;
+    let mut overflow = OverflowMode::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" if command == "asm" => {
+                output = Some(PathBuf::from(args.next().ok_or("-o expects a path")?));
+            }
+            "--trace" if command == "run" => trace = true,
+            "--max-steps" if command == "run" => {
+                let n = args.next().ok_or("--max-steps expects a number")?;
+
+                max_steps = Some(n.parse().map_err(|_| format!("invalid step count: {}", n))?);
+            }
+            "--overflow" if command == "run" => {
+                overflow = args.next().ok_or("--overflow expects a mode")?.parse()?;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+
+    let input = input.ok_or("missing input file")?;
+
+    match command.as_str() {
+        "asm" => {
+            let output = output.unwrap_or_else(|| input.with_extension("svm"));
+
+            Ok(Command::Asm { input, output })
+        }
+        "run" => Ok(Command::Run { input, trace, max_steps, overflow }),
+        "disasm" => Ok(Command::Disasm { input }),
+        _ => Ok(Command::Debug { input }),
+    }
+}
+
+/// Loads `.svm` files as bytecode and anything else as assembly source.
+/// Calling convention violations in source files are reported as warnings.
+fn load(path: &Path) -> Result<Program, String> {
+    if path.extension().is_some_and(|ext| ext == "svm") {
+        return bytecode::load_file(path).map_err(|err| format!("{}: {}", path.display(), err));
+    }
+
+    let source = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    let program = Parser::new().process(&source).map_err(|diagnostics| {
+        for diagnostic in &diagnostics {
+            eprintln!("{}\n", diagnostic);
+        }
+
+        format!("{}: could not assemble due to {} error(s)", path.display(), diagnostics.len())
+    })?;
+
+    for warning in check_convention(&program) {
+        eprintln!("warning: {}: {}", path.display(), warning);
+    }
+
+    Ok(program)
+}
+
+pub fn execute(command: Command) -> i32 {
+    match command {
+        Command::Asm { input, output } => {
+            let program = match load(&input) {
+                Ok(program) => program,
+                Err(err) => return fail(exit::INPUT, err),
+            };
+
+            match bytecode::save_file(&output, &program) {
+                Ok(()) => 0,
+                Err(err) => fail(exit::INPUT, format!("{}: {}", output.display(), err)),
+            }
+        }
+        Command::Run { input, trace, max_steps, overflow } => {
+            let program = match load(&input) {
+                Ok(program) => program,
+                Err(err) => return fail(exit::INPUT, err),
+            };
+
+            run(program, trace, max_steps, overflow)
+        }
+        Command::Disasm { input } => {
+            let program = match load(&input) {
+                Ok(program) => program,
+                Err(err) => return fail(exit::INPUT, err),
+            };
+
+            print!("{}", disassembler::disassemble(&program));
+
+            0
+        }
+        Command::Debug { input } => {
+            let program = match load(&input) {
+                Ok(program) => program,
+                Err(err) => return fail(exit::INPUT, err),
+            };
+
+            let mut debugger = Debugger::new(program);
+            install_syscalls(debugger.vm_mut());
+
+            let stdin = io::stdin();
+            match debugger.repl(stdin.lock(), &mut io::stdout()) {
+                Ok(()) => 0,
+                Err(err) => fail(exit::INPUT, err),
+            }
+        }
+    }
+}
+
+fn run(program: Program, trace: bool, max_steps: Option<u64>, overflow: OverflowMode) -> i32 {
+    let mut vm = VM::new(program.instructions, &program.data);
+    vm.overflow_mode = overflow;
+    install_syscalls(&mut vm);
+    let mut remaining = max_steps.unwrap_or(u64::MAX);
+
+    loop {
+        if trace {
+            if let Some(instruction) = vm.instructions.get(vm.pc) {
+                eprintln!("{:>6}: {}", vm.pc, instruction);
+            }
+        }
+
+        // Every instruction costs one unit, so a yield used up the budget.
+        let budget = if trace { remaining.min(1) } else { remaining };
+        match vm.run_for(budget) {
+            Ok(ExitStatus::Halted(status)) => return guest_exit(status),
+            Ok(ExitStatus::Yielded) => remaining -= budget,
+            Err(fault) => return fail(exit::FAULT, fault),
+        }
+
+        if remaining == 0 {
+            let steps = max_steps.unwrap_or(u64::MAX);
+
+            return fail(exit::STEP_LIMIT, format!("step limit of {} reached at pc {}", steps, vm.pc));
+        }
+    }
+}
+
+/// Host functions available to programs run by the driver:
+///
+/// * `syscall #1` prints `$1` as a decimal integer
+/// * `syscall #2` prints the NUL-terminated string at address `$1`
+/// * `syscall #3` stores the milliseconds since start-up in `$0`
+fn install_syscalls(vm: &mut VM) {
+    vm.register_syscall(1, "print_int", |host: &mut Host| {
+        println!("{}", host.arg(0)?);
+
+        Ok(())
+    });
+    vm.register_syscall(2, "print_str", |host: &mut Host| {
+        let bytes = host.read_cstr(host.arg(0)?)?;
+
+        io::stdout().write_all(&bytes).map_err(|err| FaultReason::Host(err.to_string()))
+    });
+
+    let start = Instant::now();
+    vm.register_syscall(3, "clock", move |host: &mut Host| {
+        host.ret(start.elapsed().as_millis() as i32);
+
+        Ok(())
+    });
+}
+
+/// Exit code for a guest that halted with `status`, see `exit`.
+fn guest_exit(status: i32) -> i32 {
+    if (0..=exit::GUEST_MAX).contains(&status) {
+        return status;
+    }
+
+    eprintln!("program halted with status {}", status);
+
+    exit::GUEST_OUT_OF_RANGE
+}
+
+fn fail<E: std::fmt::Display>(code: i32, err: E) -> i32 {
+    eprintln!("error: {}", err);
+
+    code
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Ok(Command::Asm { input: "in.s".into(), output: "in.svm".into() }),
+            parse_args(args("asm in.s")),
+        );
+        assert_eq!(
+            Ok(Command::Asm { input: "in.s".into(), output: "out.svm".into() }),
+            parse_args(args("asm in.s -o out.svm")),
+        );
+        assert_eq!(
+            Ok(Command::Run { input: "a.svm".into(), trace: true, max_steps: Some(10), overflow: OverflowMode::Wrapping }),
+            parse_args(args("run --trace a.svm --max-steps 10")),
+        );
+        assert_eq!(
+            Ok(Command::Run { input: "a.s".into(), trace: false, max_steps: None, overflow: OverflowMode::Trapping }),
+            parse_args(args("run a.s --overflow trap")),
+        );
+        assert_eq!(Ok(Command::Disasm { input: "a.svm".into() }), parse_args(args("disasm a.svm")));
+        assert_eq!(Ok(Command::Debug { input: "a.s".into() }), parse_args(args("debug a.s")));
+
+        assert!(parse_args(args("")).is_err());
+        assert!(parse_args(args("run")).is_err());
+        assert!(parse_args(args("run a.s b.s")).is_err());
+        assert!(parse_args(args("run a.s --max-steps many")).is_err());
+        assert!(parse_args(args("disasm a.svm --trace")).is_err());
+        assert!(parse_args(args("run a.s --overflow ignore")).is_err());
+        assert!(parse_args(args("fly a.s")).is_err());
+    }
+
+    #[test]
+    fn guest_status() {
+        assert_eq!(0, guest_exit(0));
+        assert_eq!(62, guest_exit(62));
+        assert_eq!(exit::GUEST_OUT_OF_RANGE, guest_exit(70));
+        assert_eq!(exit::GUEST_OUT_OF_RANGE, guest_exit(-1));
+    }
+}
@@ -0,0 +1,349 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    assembler::{SymbolTable, SymbolType},
+    instruction::Instruction,
+    program::Program,
+    vm::{ExitStatus, VmFault, VM},
+};
+
+const HELP: &str = "commands:
+    break <label|pc>    set a breakpoint            (b)
+    delete <label|pc>   remove a breakpoint         (d)
+    step                execute one instruction     (s)
+    next                step over calls             (n)
+    continue            run to the next breakpoint  (c)
+    finish              run until the current function returns (f)
+    regs                print registers and flags   (r)
+    backtrace           print the call stack        (bt)
+    quit                leave the debugger          (q)";
+
+/// Why execution stopped and control went back to the user.
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Stepped,
+    Breakpoint(usize),
+    Returned,
+    Finished(Result<ExitStatus, VmFault>),
+}
+
+pub struct Debugger {
+    vm: VM,
+    symbols: SymbolTable,
+    breakpoints: BTreeSet<usize>,
+    finished: Option<Result<ExitStatus, VmFault>>,
+}
+
+impl Debugger {
+    pub fn new(program: Program) -> Self {
+        Self {
+            vm: VM::new(program.instructions, &program.data),
+            symbols: program.symbols,
+            breakpoints: BTreeSet::new(),
+            finished: None,
+        }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    /// Mutable access to the VM, e.g. to register syscalls before running.
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    /// Resolves a label name or a numeric pc.
+    pub fn resolve(&self, location: &str) -> Option<usize> {
+        self.symbols.get_offset(location).or_else(|| location.parse().ok())
+    }
+
+    /// `label` or `label+offset` of the closest label at or before `pc`,
+    /// code before the first label is relative to `entry`.
+    pub fn describe(&self, pc: usize) -> String {
+        let closest = self.symbols.iter()
+            .filter_map(|(name, stype)| match *stype {
+                SymbolType::Label(offset) if offset <= pc => Some((offset, name)),
+                _ => None,
+            })
+            .max_by_key(|(offset, _)| *offset);
+
+        match closest {
+            Some((offset, name)) if offset == pc => name.to_string(),
+            Some((offset, name)) => format!("{}+{}", name, pc - offset),
+            None if pc == 0 => "entry".to_string(),
+            None => format!("entry+{}", pc),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.insert(pc)
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    fn depth(&self) -> usize {
+        self.vm.stack.len() / 2
+    }
+
+    /// Executes instructions until `stop` holds, a breakpoint is reached
+    /// or the program finishes. The instruction at the current pc is always
+    /// executed, so resuming from a breakpoint does not stop right away.
+    fn resume<F: Fn(&Self) -> Option<Event>>(&mut self, stop: F) -> Event {
+        if let Some(result) = &self.finished {
+            return Event::Finished(result.clone());
+        }
+
+        loop {
+            let result = match self.vm.step() {
+                Ok(None) => None,
+                Ok(Some(status)) => Some(Ok(status)),
+                Err(fault) => Some(Err(fault)),
+            };
+
+            if let Some(result) = result {
+                self.finished = Some(result.clone());
+
+                return Event::Finished(result);
+            }
+
+            if let Some(event) = stop(self) {
+                return event;
+            }
+
+            if self.breakpoints.contains(&self.vm.pc) {
+                return Event::Breakpoint(self.vm.pc);
+            }
+        }
+    }
+
+    pub fn step(&mut self) -> Event {
+        self.resume(|_| Some(Event::Stepped))
+    }
+
+    /// Like `step`, but runs a `CALL` until it returns.
+    pub fn step_over(&mut self) -> Event {
+        let depth = self.depth();
+
+        self.resume(|dbg| if dbg.depth() <= depth { Some(Event::Stepped) } else { None })
+    }
+
+    pub fn cont(&mut self) -> Event {
+        self.resume(|_| None)
+    }
+
+    /// Runs until the current function executes its `RET`.
+    pub fn finish(&mut self) -> Event {
+        let depth = self.depth();
+
+        self.resume(|dbg| if dbg.depth() < depth { Some(Event::Returned) } else { None })
+    }
+
+    pub fn write_registers<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for (i, chunk) in self.vm.ir.chunks(8).enumerate() {
+            let line = chunk.iter().enumerate()
+                .map(|(j, value)| format!("${:<2} {:>11}", i * 8 + j, value))
+                .collect::<Vec<_>>();
+
+            writeln!(out, "{}", line.join("  "))?;
+        }
+
+        for (i, chunk) in self.vm.fr.chunks(8).enumerate() {
+            let line = chunk.iter().enumerate()
+                .map(|(j, value)| format!("$f{:<2} {:>10}", i * 8 + j, value))
+                .collect::<Vec<_>>();
+
+            writeln!(out, "{}", line.join("  "))?;
+        }
+
+        writeln!(
+            out,
+            "pc {}  sp {}  bp {}  compare_flag {}  carry_flag {}  overflow_flag {}  remainder {}  loops {:?}",
+            self.vm.pc, self.vm.sp, self.vm.bp, self.vm.compare_flag, self.vm.carry_flag, self.vm.overflow_flag,
+            self.vm.remainder, self.vm.loops,
+        )
+    }
+
+    pub fn write_backtrace<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "#0 {:>6} in {}", self.vm.pc, self.describe(self.vm.pc))?;
+
+        let returns = self.vm.stack.iter().step_by(2).rev();
+        for (frame, ret) in returns.enumerate() {
+            let call = ret.saturating_sub(1);
+
+            writeln!(out, "#{} {:>6} in {}", frame + 1, call, self.describe(call))?;
+        }
+
+        Ok(())
+    }
+
+    fn write_location<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match self.vm.instructions.get(self.vm.pc) {
+            Some(instruction) => writeln!(out, "{:>6} <{}>: {}", self.vm.pc, self.describe(self.vm.pc), Listing(instruction, self)),
+            None => writeln!(out, "{:>6}: <end of program>", self.vm.pc),
+        }
+    }
+
+    fn write_event<W: Write>(&self, out: &mut W, event: Event) -> io::Result<()> {
+        match event {
+            Event::Breakpoint(pc) => writeln!(out, "breakpoint at {} <{}>", pc, self.describe(pc))?,
+            Event::Finished(Ok(ExitStatus::Halted(code))) => return writeln!(out, "program halted with status {}", code),
+            Event::Finished(Err(fault)) => return writeln!(out, "{}", fault),
+            Event::Stepped | Event::Returned | Event::Finished(Ok(ExitStatus::Yielded)) => {}
+        }
+
+        self.write_location(out)
+    }
+
+    /// Reads commands from `input` until `quit` or end of input.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> io::Result<()> {
+        self.write_location(out)?;
+        write!(out, "(svm) ")?;
+        out.flush()?;
+
+        for line in input.lines() {
+            let line = line?;
+            let mut words = line.split_whitespace();
+
+            match (words.next(), words.next()) {
+                (None, _) => {}
+                (Some("q"), _) | (Some("quit"), _) => return Ok(()),
+                (Some("h"), _) | (Some("help"), _) => writeln!(out, "{}", HELP)?,
+                (Some("s"), _) | (Some("step"), _) => {
+                    let event = self.step();
+                    self.write_event(out, event)?;
+                }
+                (Some("n"), _) | (Some("next"), _) => {
+                    let event = self.step_over();
+                    self.write_event(out, event)?;
+                }
+                (Some("c"), _) | (Some("continue"), _) => {
+                    let event = self.cont();
+                    self.write_event(out, event)?;
+                }
+                (Some("f"), _) | (Some("finish"), _) => {
+                    let event = self.finish();
+                    self.write_event(out, event)?;
+                }
+                (Some("r"), _) | (Some("regs"), _) => self.write_registers(out)?,
+                (Some("bt"), _) | (Some("backtrace"), _) => self.write_backtrace(out)?,
+                (Some("b"), Some(location)) | (Some("break"), Some(location)) => match self.resolve(location) {
+                    Some(pc) => {
+                        self.add_breakpoint(pc);
+                        writeln!(out, "breakpoint set at {} <{}>", pc, self.describe(pc))?;
+                    }
+                    None => writeln!(out, "unknown location `{}`", location)?,
+                },
+                (Some("d"), Some(location)) | (Some("delete"), Some(location)) => {
+                    match self.resolve(location).filter(|pc| self.remove_breakpoint(*pc)) {
+                        Some(pc) => writeln!(out, "breakpoint at {} removed", pc)?,
+                        None => writeln!(out, "no breakpoint at `{}`", location)?,
+                    }
+                }
+                (Some(command), _) => writeln!(out, "unknown command `{}`, try `help`", command)?,
+            }
+
+            write!(out, "(svm) ")?;
+            out.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Instruction with its jump target shown by label name.
+struct Listing<'a>(&'a Instruction, &'a Debugger);
+
+impl std::fmt::Display for Listing<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.target() {
+            Some(dst) => write!(f, "{} @{}", self.0.mnemonic(), self.1.describe(dst)),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::Parser;
+
+    const CODE: &str = "
+.data
+.code
+load $0 #1
+call @double
+call @double
+hlt
+double:
+add $0 $0 $0
+call @noop
+ret
+noop:
+ret
+";
+
+    fn debugger() -> Debugger {
+        Debugger::new(Parser::new().process(CODE).unwrap())
+    }
+
+    #[test]
+    fn step_next_finish() {
+        let mut dbg = debugger();
+
+        assert_eq!(Event::Stepped, dbg.step());
+        assert_eq!(Event::Stepped, dbg.step());
+        assert_eq!(4, dbg.vm().pc);
+        assert_eq!("double", dbg.describe(4));
+
+        assert_eq!(Event::Stepped, dbg.step());
+        assert_eq!(Event::Stepped, dbg.step_over());
+        assert_eq!((6, 2), (dbg.vm().pc, dbg.vm().ir[0]));
+
+        assert_eq!(Event::Returned, dbg.finish());
+        assert_eq!(2, dbg.vm().pc);
+
+        assert_eq!(Event::Stepped, dbg.step_over());
+        assert_eq!((3, 4), (dbg.vm().pc, dbg.vm().ir[0]));
+        assert_eq!(Event::Finished(Ok(ExitStatus::Halted(4))), dbg.cont());
+        assert_eq!(Event::Finished(Ok(ExitStatus::Halted(4))), dbg.step());
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut dbg = debugger();
+        let noop = dbg.resolve("noop").unwrap();
+
+        assert!(dbg.add_breakpoint(noop));
+        assert_eq!(Event::Breakpoint(noop), dbg.cont());
+        assert_eq!(Event::Breakpoint(noop), dbg.cont());
+        assert_eq!(4, dbg.vm().ir[0]);
+
+        assert!(dbg.remove_breakpoint(noop));
+        assert_eq!(Event::Finished(Ok(ExitStatus::Halted(4))), dbg.cont());
+    }
+
+    #[test]
+    fn repl() {
+        let mut dbg = debugger();
+        let mut out = Vec::new();
+
+        dbg.repl("b noop\nc\nbt\nr\nfinish\nbogus\nd noop\nc\n".as_bytes(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("breakpoint set at 7 <noop>"), "{}", out);
+        assert!(out.contains("breakpoint at 7 <noop>\n     7 <noop>: ret"), "{}", out);
+        assert!(out.contains("#0      7 in noop\n#1      5 in double+1\n#2      1 in entry+1\n"), "{}", out);
+        assert!(out.contains("compare_flag false"), "{}", out);
+        assert!(out.contains("     6 <double+2>: ret"), "{}", out);
+        assert!(out.contains("unknown command `bogus`"), "{}", out);
+        assert!(out.contains("breakpoint at 7 removed"), "{}", out);
+        assert!(out.contains("program halted with status 4"), "{}", out);
+    }
+}
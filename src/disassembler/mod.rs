@@ -0,0 +1,446 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+};
+
+use crate::{
+    assembler::SymbolType,
+    instruction::Instruction,
+    program::Program,
+};
+
+/// Assembly listing of a program that the assembler accepts again.
+///
+/// Jump, call and loop targets use the label names from the program's
+/// symbol table, targets without a symbol get an `L<pc>` label.
+pub struct Disassembly<'a> {
+    program: &'a Program,
+    labels: BTreeMap<usize, String>,
+    tables: BTreeMap<usize, String>,
+}
+
+impl<'a> Disassembly<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let mut labels = BTreeMap::new();
+        let mut tables = BTreeMap::new();
+        let mut targets = program.instructions.iter()
+            .filter_map(|instruction| match *instruction {
+                Instruction::SWITCH { default, .. } => Some(default),
+                _ => instruction.target(),
+            })
+            .collect::<Vec<_>>();
+
+        for (name, stype) in program.symbols.iter() {
+            match *stype {
+                SymbolType::Label(pc) => {
+                    labels.entry(pc).or_insert_with(|| name.to_string());
+                }
+                SymbolType::JumpTable { addr, len } => {
+                    tables.entry(addr).or_insert_with(|| name.to_string());
+                    targets.extend(program.jump_table(addr, len));
+                }
+                _ => {}
+            }
+        }
+
+        for dst in targets {
+            labels.entry(dst).or_insert_with(|| {
+                let mut name = format!("L{}", dst);
+                let mut n = 0;
+                while program.symbols.contains(&name) {
+                    n += 1;
+                    name = format!("L{}x{}", dst, n);
+                }
+
+                name
+            });
+        }
+
+        Self { program, labels, tables }
+    }
+
+    /// Name of the label at `pc`, if any.
+    pub fn label(&self, pc: usize) -> Option<&str> {
+        self.labels.get(&pc).map(String::as_str)
+    }
+
+    fn label_or_default(&self, pc: usize) -> String {
+        self.labels.get(&pc).cloned().unwrap_or_else(|| format!("L{}", pc))
+    }
+
+    fn write_data(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries = self.program.symbols.iter()
+            .filter_map(|(name, stype)| match *stype {
+                SymbolType::Integer { value, addr } => Some((addr, format!("{}: .integer #{}", name, value))),
+                SymbolType::Data { addr, len } => {
+                    let bytes = addr.checked_add(len)
+                        .and_then(|end| self.program.data.get(addr..end))
+                        .unwrap_or_default();
+
+                    Some((addr, format!("{}: {}", name, DataBytes(bytes))))
+                }
+                SymbolType::JumpTable { addr, len } => {
+                    let targets = self.program.jump_table(addr, len).into_iter()
+                        .map(|pc| format!("@{}", self.label_or_default(pc)))
+                        .collect::<Vec<_>>();
+
+                    Some((addr, format!(".jumptable {}: {}", name, targets.join(", "))))
+                }
+                SymbolType::Label(_) => None,
+            })
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        for (_, decl) in entries {
+            writeln!(f, "{}", decl)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Disassembly<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, ".data")?;
+        self.write_data(f)?;
+        writeln!(f, ".code")?;
+
+        for (pc, instruction) in self.program.instructions.iter().enumerate() {
+            if let Some(label) = self.label(pc) {
+                writeln!(f, "{}:", label)?;
+            }
+
+            write_instruction(f, instruction, |dst| self.label_or_default(dst), |addr| {
+                self.tables.get(&addr).cloned().unwrap_or_else(|| format!("T{}", addr))
+            })?;
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats a single instruction, jump targets are printed as `@L<pc>` and
+/// jump tables as `@T<addr>`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_instruction(f, self, |dst| format!("L{}", dst), |addr| format!("T{}", addr))
+    }
+}
+
+/// Directive that declares `bytes` again: `.asciiz` or `.ascii` for
+/// UTF-8 text, including no bytes at all, `.bytes` for anything else.
+struct DataBytes<'a>(&'a [u8]);
+
+impl fmt::Display for DataBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.split_last() {
+            Some((0, text)) if std::str::from_utf8(text).is_ok() => {
+                write!(f, ".asciiz \"{}\"", std::str::from_utf8(text).unwrap().escape_debug())
+            }
+            _ => match std::str::from_utf8(self.0) {
+                Ok(text) => write!(f, ".ascii \"{}\"", text.escape_debug()),
+                _ => {
+                    let bytes = self.0.iter().map(|b| format!("#{}", b)).collect::<Vec<_>>();
+
+                    write!(f, ".bytes {}", bytes.join(", "))
+                }
+            },
+        }
+    }
+}
+
+struct Offset(i32);
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            0 => Ok(()),
+            o if o > 0 => write!(f, "+{}", o),
+            o => write!(f, "{}", o),
+        }
+    }
+}
+
+/// Float immediate the assembler reads back bit for bit: the shortest
+/// round-tripping digits, always with a decimal point.
+struct Float(f32);
+
+impl fmt::Display for Float {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = self.0.to_string();
+
+        if s.contains('.') {
+            write!(f, "{}", s)
+        } else {
+            write!(f, "{}.0", s)
+        }
+    }
+}
+
+fn write_instruction<L, T>(f: &mut fmt::Formatter<'_>, instruction: &Instruction, label: L, table: T) -> fmt::Result
+    where L: Fn(usize) -> String, T: Fn(usize) -> String
+{
+    let op = instruction.mnemonic();
+
+    match *instruction {
+        Instruction::IGL | Instruction::HLT | Instruction::RET => write!(f, "{}", op),
+        Instruction::JMP { dst } |
+        Instruction::JMPE { dst } |
+        Instruction::JMPNE { dst } |
+        Instruction::JMPC { dst } |
+        Instruction::JMPO { dst } |
+        Instruction::CALL { dst } |
+        Instruction::LOOP { dst } => write!(f, "{} @{}", op, label(dst)),
+        Instruction::SWITCH { r, table: addr, default, .. } => {
+            write!(f, "{} ${} @{} @{}", op, r, table(addr), label(default))
+        }
+        Instruction::CLOOP { count } |
+        Instruction::SYSCALL { n: count } => write!(f, "{} #{}", op, count),
+        Instruction::INC { r } |
+        Instruction::CLOOPR { r } |
+        Instruction::JMPR { r } |
+        Instruction::CALLR { r } => write!(f, "{} ${}", op, r),
+        Instruction::LOAD { rd, value } => write!(f, "{} ${} #{}", op, rd, value),
+        Instruction::LOADM { rd, base, offset, .. } => write!(f, "{} ${} [${}{}]", op, rd, base, Offset(offset)),
+        Instruction::STOREM { rs, base, offset, .. } => write!(f, "{} ${} [${}{}]", op, rs, base, Offset(offset)),
+        Instruction::LOADBP { rd, offset, .. } => write!(f, "{} ${} [bp{}]", op, rd, Offset(offset)),
+        Instruction::STOREBP { rs, offset, .. } => write!(f, "{} ${} [bp{}]", op, rs, Offset(offset)),
+        Instruction::PUSH { rs } => write!(f, "{} ${}", op, rs),
+        Instruction::POP { rd } => write!(f, "{} ${}", op, rd),
+        Instruction::ADD { rd, rl, rh } |
+        Instruction::SUB { rd, rl, rh } |
+        Instruction::MUL { rd, rl, rh } |
+        Instruction::DIV { rd, rl, rh } |
+        Instruction::MOD { rd, rl, rh } |
+        Instruction::DIVU { rd, rl, rh } |
+        Instruction::MODU { rd, rl, rh } |
+        Instruction::ADDC { rd, rl, rh } |
+        Instruction::SUBB { rd, rl, rh } |
+        Instruction::AND { rd, rl, rh } |
+        Instruction::OR { rd, rl, rh } |
+        Instruction::XOR { rd, rl, rh } |
+        Instruction::SHL { rd, rl, rh } |
+        Instruction::SHR { rd, rl, rh } |
+        Instruction::SAR { rd, rl, rh } => write!(f, "{} ${} ${} ${}", op, rd, rl, rh),
+        Instruction::DIVMOD { rq, rr, rl, rh } => write!(f, "{} ${} ${} ${} ${}", op, rq, rr, rl, rh),
+        Instruction::NOT { rd, rs } => write!(f, "{} ${} ${}", op, rd, rs),
+        Instruction::ANDI { rd, rl, value } |
+        Instruction::ORI { rd, rl, value } |
+        Instruction::XORI { rd, rl, value } |
+        Instruction::SHLI { rd, rl, value } |
+        Instruction::SHRI { rd, rl, value } |
+        Instruction::SARI { rd, rl, value } => write!(f, "{} ${} ${} #{}", op, rd, rl, value),
+        Instruction::EQ { rl, rh } |
+        Instruction::NEQ { rl, rh } |
+        Instruction::GTE { rl, rh } |
+        Instruction::LTE { rl, rh } |
+        Instruction::LT { rl, rh } |
+        Instruction::GT { rl, rh } => write!(f, "{} ${} ${}", op, rl, rh),
+        Instruction::LOADF { fd, value } => write!(f, "{} $f{} #{}", op, fd, Float(value)),
+        Instruction::FADD { fd, fl, fh } |
+        Instruction::FSUB { fd, fl, fh } |
+        Instruction::FMUL { fd, fl, fh } |
+        Instruction::FDIV { fd, fl, fh } => write!(f, "{} $f{} $f{} $f{}", op, fd, fl, fh),
+        Instruction::FEQ { fl, fh } |
+        Instruction::FNEQ { fl, fh } |
+        Instruction::FGTE { fl, fh } |
+        Instruction::FLTE { fl, fh } |
+        Instruction::FLT { fl, fh } |
+        Instruction::FGT { fl, fh } => write!(f, "{} $f{} $f{}", op, fl, fh),
+        Instruction::ITOF { fd, rs } => write!(f, "{} $f{} ${}", op, fd, rs),
+        Instruction::FTOI { rd, fs } => write!(f, "{} ${} $f{}", op, rd, fs),
+    }
+}
+
+pub fn disassemble(program: &Program) -> String {
+    Disassembly::new(program).to_string()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        assembler::Parser,
+        instruction::Width,
+    };
+    use proptest::prelude::*;
+
+    fn register() -> impl Strategy<Value = usize> {
+        0usize..32
+    }
+
+    fn width() -> impl Strategy<Value = Width> {
+        prop_oneof![Just(Width::Byte), Just(Width::Half), Just(Width::Word)]
+    }
+
+    fn float() -> impl Strategy<Value = f32> {
+        use proptest::num::f32::{NEGATIVE, NORMAL, POSITIVE, SUBNORMAL, ZERO};
+
+        POSITIVE | NEGATIVE | NORMAL | SUBNORMAL | ZERO
+    }
+
+    fn instruction(len: usize) -> impl Strategy<Value = Instruction> {
+        let dst = 0..len;
+
+        prop_oneof![
+            Just(Instruction::IGL),
+            Just(Instruction::HLT),
+            Just(Instruction::RET),
+            dst.clone().prop_map(|dst| Instruction::JMP { dst }),
+            dst.clone().prop_map(|dst| Instruction::JMPE { dst }),
+            dst.clone().prop_map(|dst| Instruction::JMPNE { dst }),
+            dst.clone().prop_map(|dst| Instruction::JMPC { dst }),
+            dst.clone().prop_map(|dst| Instruction::JMPO { dst }),
+            dst.clone().prop_map(|dst| Instruction::CALL { dst }),
+            dst.prop_map(|dst| Instruction::LOOP { dst }),
+            (0..=u32::MAX as usize).prop_map(|count| Instruction::CLOOP { count }),
+            (0..=u32::MAX as usize).prop_map(|n| Instruction::SYSCALL { n }),
+            register().prop_map(|r| Instruction::INC { r }),
+            register().prop_map(|r| Instruction::CLOOPR { r }),
+            register().prop_map(|r| Instruction::JMPR { r }),
+            register().prop_map(|r| Instruction::CALLR { r }),
+            (register(), any::<i32>()).prop_map(|(rd, value)| Instruction::LOAD { rd, value }),
+            (width(), register(), register(), any::<i32>())
+                .prop_map(|(width, rd, base, offset)| Instruction::LOADM { width, rd, base, offset }),
+            (width(), register(), register(), any::<i32>())
+                .prop_map(|(width, rs, base, offset)| Instruction::STOREM { width, rs, base, offset }),
+            (width(), register(), any::<i32>()).prop_map(|(width, rd, offset)| Instruction::LOADBP { width, rd, offset }),
+            (width(), register(), any::<i32>()).prop_map(|(width, rs, offset)| Instruction::STOREBP { width, rs, offset }),
+            register().prop_map(|rs| Instruction::PUSH { rs }),
+            register().prop_map(|rd| Instruction::POP { rd }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::ADD { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::SUB { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::MUL { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::DIV { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::MOD { rd, rl, rh }),
+            (register(), register(), register(), register())
+                .prop_map(|(rq, rr, rl, rh)| Instruction::DIVMOD { rq, rr, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::DIVU { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::MODU { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::ADDC { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::SUBB { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::AND { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::OR { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::XOR { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::SHL { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::SHR { rd, rl, rh }),
+            (register(), register(), register()).prop_map(|(rd, rl, rh)| Instruction::SAR { rd, rl, rh }),
+            (register(), register()).prop_map(|(rd, rs)| Instruction::NOT { rd, rs }),
+            (register(), register(), any::<i32>()).prop_map(|(rd, rl, value)| Instruction::ANDI { rd, rl, value }),
+            (register(), register(), any::<i32>()).prop_map(|(rd, rl, value)| Instruction::ORI { rd, rl, value }),
+            (register(), register(), any::<i32>()).prop_map(|(rd, rl, value)| Instruction::XORI { rd, rl, value }),
+            (register(), register(), any::<i32>()).prop_map(|(rd, rl, value)| Instruction::SHLI { rd, rl, value }),
+            (register(), register(), any::<i32>()).prop_map(|(rd, rl, value)| Instruction::SHRI { rd, rl, value }),
+            (register(), register(), any::<i32>()).prop_map(|(rd, rl, value)| Instruction::SARI { rd, rl, value }),
+            (register(), register()).prop_map(|(rl, rh)| Instruction::EQ { rl, rh }),
+            (register(), register()).prop_map(|(rl, rh)| Instruction::NEQ { rl, rh }),
+            (register(), register()).prop_map(|(rl, rh)| Instruction::GTE { rl, rh }),
+            (register(), register()).prop_map(|(rl, rh)| Instruction::LTE { rl, rh }),
+            (register(), register()).prop_map(|(rl, rh)| Instruction::LT { rl, rh }),
+            (register(), register()).prop_map(|(rl, rh)| Instruction::GT { rl, rh }),
+            (register(), float()).prop_map(|(fd, value)| Instruction::LOADF { fd, value }),
+            (register(), register(), register()).prop_map(|(fd, fl, fh)| Instruction::FADD { fd, fl, fh }),
+            (register(), register(), register()).prop_map(|(fd, fl, fh)| Instruction::FSUB { fd, fl, fh }),
+            (register(), register(), register()).prop_map(|(fd, fl, fh)| Instruction::FMUL { fd, fl, fh }),
+            (register(), register(), register()).prop_map(|(fd, fl, fh)| Instruction::FDIV { fd, fl, fh }),
+            (register(), register()).prop_map(|(fl, fh)| Instruction::FEQ { fl, fh }),
+            (register(), register()).prop_map(|(fl, fh)| Instruction::FNEQ { fl, fh }),
+            (register(), register()).prop_map(|(fl, fh)| Instruction::FGTE { fl, fh }),
+            (register(), register()).prop_map(|(fl, fh)| Instruction::FLTE { fl, fh }),
+            (register(), register()).prop_map(|(fl, fh)| Instruction::FLT { fl, fh }),
+            (register(), register()).prop_map(|(fl, fh)| Instruction::FGT { fl, fh }),
+            (register(), register()).prop_map(|(fd, rs)| Instruction::ITOF { fd, rs }),
+            (register(), register()).prop_map(|(rd, fs)| Instruction::FTOI { rd, fs }),
+        ]
+    }
+
+    fn program() -> impl Strategy<Value = Vec<Instruction>> {
+        (1usize..48).prop_flat_map(|len| proptest::collection::vec(instruction(len), len))
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip(instructions in program()) {
+            let program = Program { instructions, ..Program::default() };
+            let source = disassemble(&program);
+
+            prop_assert_eq!(program.instructions, Parser::new().process(&source).unwrap().instructions);
+        }
+    }
+
+    #[test]
+    fn jump_tables() {
+        let program = Parser::new().process("
+.data
+.jumptable ops: @a, @b, @a
+.code
+switch $0 @ops @b
+a:
+hlt
+b:
+hlt
+").unwrap();
+        let source = disassemble(&program);
+
+        assert!(source.contains(".jumptable ops: @a, @b, @a\n"), "{}", source);
+        assert!(source.contains("switch $0 @ops @b\n"), "{}", source);
+        assert_eq!(Ok(program), Parser::new().process(&source));
+
+        // A length read from a malformed file must not be walked.
+        let mut program = Program { instructions: vec![Instruction::HLT], ..Program::default() };
+        program.symbols.add("ops".to_string(), SymbolType::JumpTable { addr: 0, len: usize::MAX >> 2 });
+        let start = std::time::Instant::now();
+
+        assert!(disassemble(&program).contains(".jumptable ops: \n"));
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn label_collisions() {
+        let mut program = Parser::new().process("
+.data
+.code
+L2:
+jmp @L2x1
+L2x1:
+jmp @L2
+hlt
+").unwrap();
+        // Nothing names the `hlt`, and both obvious names are taken.
+        program.instructions[1] = Instruction::JMP { dst: 2 };
+        let source = disassemble(&program);
+
+        assert!(source.contains("L2x2:\nhlt\n"), "{}", source);
+        assert_eq!(program.instructions, Parser::new().process(&source).unwrap().instructions);
+    }
+
+    #[test]
+    fn keeps_symbols() {
+        let program = Parser::new().process("
+.data
+msg: .asciiz \"hello, \\\"world\\\"!\\n\"
+answer: .integer #42
+raw: .bytes #0xFF, 'ok', #-1
+tail: .ascii 'caf\\u{e9}\\t'
+empty: .ascii ''
+
+.code
+load $0 @msg
+start:
+loadb $1 [$0-2]
+call @start
+storew $1 [$0+8]
+hlt
+").unwrap();
+        let source = disassemble(&program);
+
+        assert!(source.contains("start:\nloadb $1 [$0-2]\ncall @start\n"));
+        assert!(source.contains("msg: .asciiz \"hello, \\\"world\\\"!\\n\"\n"), "{}", source);
+        assert!(source.contains("raw: .bytes #255, #111, #107, #255\n"), "{}", source);
+        assert!(source.contains("tail: .ascii \"café\\t\"\n"), "{}", source);
+        assert!(source.contains("empty: .ascii \"\"\n"), "{}", source);
+        assert_eq!(Ok(program), Parser::new().process(&source));
+    }
+}
@@ -1,4 +1,22 @@
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Width {
+    Byte,
+    Half,
+    Word,
+}
+
+impl Width {
+    pub fn bytes(self) -> usize {
+        match self {
+            Width::Byte => 1,
+            Width::Half => 2,
+            Width::Word => 4,
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Instruction {
     IGL,
     HLT,
@@ -6,19 +24,189 @@ pub enum Instruction {
     JMP { dst: usize },
     JMPE { dst: usize },
     JMPNE { dst: usize },
+    JMPC { dst: usize },
+    JMPO { dst: usize },
     CALL { dst: usize },
+    JMPR { r: usize },
+    CALLR { r: usize },
+    SWITCH { r: usize, table: usize, len: usize, default: usize },
     CLOOP { count: usize },
+    CLOOPR { r: usize },
     LOOP { dst: usize },
+    SYSCALL { n: usize },
     INC { r: usize },
     LOAD { rd: usize, value: i32 },
+    LOADM { width: Width, rd: usize, base: usize, offset: i32 },
+    STOREM { width: Width, rs: usize, base: usize, offset: i32 },
+    LOADBP { width: Width, rd: usize, offset: i32 },
+    STOREBP { width: Width, rs: usize, offset: i32 },
+    PUSH { rs: usize },
+    POP { rd: usize },
     ADD { rd: usize, rl: usize, rh: usize },
     SUB { rd: usize, rl: usize, rh: usize },
     MUL { rd: usize, rl: usize, rh: usize },
     DIV { rd: usize, rl: usize, rh: usize },
+    MOD { rd: usize, rl: usize, rh: usize },
+    DIVMOD { rq: usize, rr: usize, rl: usize, rh: usize },
+    DIVU { rd: usize, rl: usize, rh: usize },
+    MODU { rd: usize, rl: usize, rh: usize },
+    ADDC { rd: usize, rl: usize, rh: usize },
+    SUBB { rd: usize, rl: usize, rh: usize },
+    AND { rd: usize, rl: usize, rh: usize },
+    OR { rd: usize, rl: usize, rh: usize },
+    XOR { rd: usize, rl: usize, rh: usize },
+    SHL { rd: usize, rl: usize, rh: usize },
+    SHR { rd: usize, rl: usize, rh: usize },
+    SAR { rd: usize, rl: usize, rh: usize },
+    NOT { rd: usize, rs: usize },
+    ANDI { rd: usize, rl: usize, value: i32 },
+    ORI { rd: usize, rl: usize, value: i32 },
+    XORI { rd: usize, rl: usize, value: i32 },
+    SHLI { rd: usize, rl: usize, value: i32 },
+    SHRI { rd: usize, rl: usize, value: i32 },
+    SARI { rd: usize, rl: usize, value: i32 },
     EQ { rl: usize, rh: usize },
     NEQ { rl: usize, rh: usize },
     GTE { rl: usize, rh: usize },
     LTE { rl: usize, rh: usize },
     LT { rl: usize, rh: usize },
     GT { rl: usize, rh: usize },
+    LOADF { fd: usize, value: f32 },
+    FADD { fd: usize, fl: usize, fh: usize },
+    FSUB { fd: usize, fl: usize, fh: usize },
+    FMUL { fd: usize, fl: usize, fh: usize },
+    FDIV { fd: usize, fl: usize, fh: usize },
+    FEQ { fl: usize, fh: usize },
+    FNEQ { fl: usize, fh: usize },
+    FGTE { fl: usize, fh: usize },
+    FLTE { fl: usize, fh: usize },
+    FLT { fl: usize, fh: usize },
+    FGT { fl: usize, fh: usize },
+    ITOF { fd: usize, rs: usize },
+    FTOI { rd: usize, fs: usize },
+}
+
+impl Instruction {
+    /// Assembly mnemonic of the instruction, as accepted by the assembler.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::IGL => "igl",
+            Instruction::HLT => "hlt",
+            Instruction::RET => "ret",
+            Instruction::JMP { .. } | Instruction::JMPR { .. } => "jmp",
+            Instruction::JMPE { .. } => "jmpe",
+            Instruction::JMPNE { .. } => "jmpne",
+            Instruction::JMPC { .. } => "jmpc",
+            Instruction::JMPO { .. } => "jmpo",
+            Instruction::CALL { .. } | Instruction::CALLR { .. } => "call",
+            Instruction::SWITCH { .. } => "switch",
+            Instruction::CLOOP { .. } | Instruction::CLOOPR { .. } => "cloop",
+            Instruction::LOOP { .. } => "loop",
+            Instruction::SYSCALL { .. } => "syscall",
+            Instruction::INC { .. } => "inc",
+            Instruction::LOAD { .. } => "load",
+            Instruction::LOADM { width: Width::Byte, .. } |
+            Instruction::LOADBP { width: Width::Byte, .. } => "loadb",
+            Instruction::LOADM { width: Width::Half, .. } |
+            Instruction::LOADBP { width: Width::Half, .. } => "loadh",
+            Instruction::LOADM { width: Width::Word, .. } |
+            Instruction::LOADBP { width: Width::Word, .. } => "loadw",
+            Instruction::STOREM { width: Width::Byte, .. } |
+            Instruction::STOREBP { width: Width::Byte, .. } => "storeb",
+            Instruction::STOREM { width: Width::Half, .. } |
+            Instruction::STOREBP { width: Width::Half, .. } => "storeh",
+            Instruction::STOREM { width: Width::Word, .. } |
+            Instruction::STOREBP { width: Width::Word, .. } => "storew",
+            Instruction::PUSH { .. } => "push",
+            Instruction::POP { .. } => "pop",
+            Instruction::ADD { .. } => "add",
+            Instruction::SUB { .. } => "sub",
+            Instruction::MUL { .. } => "mul",
+            Instruction::DIV { .. } => "div",
+            Instruction::MOD { .. } => "mod",
+            Instruction::DIVMOD { .. } => "divmod",
+            Instruction::DIVU { .. } => "divu",
+            Instruction::MODU { .. } => "modu",
+            Instruction::ADDC { .. } => "addc",
+            Instruction::SUBB { .. } => "subb",
+            Instruction::AND { .. } | Instruction::ANDI { .. } => "and",
+            Instruction::OR { .. } | Instruction::ORI { .. } => "or",
+            Instruction::XOR { .. } | Instruction::XORI { .. } => "xor",
+            Instruction::SHL { .. } | Instruction::SHLI { .. } => "shl",
+            Instruction::SHR { .. } | Instruction::SHRI { .. } => "shr",
+            Instruction::SAR { .. } | Instruction::SARI { .. } => "sar",
+            Instruction::NOT { .. } => "not",
+            Instruction::EQ { .. } => "eq",
+            Instruction::NEQ { .. } => "neq",
+            Instruction::GTE { .. } => "gte",
+            Instruction::LTE { .. } => "lte",
+            Instruction::LT { .. } => "lt",
+            Instruction::GT { .. } => "gt",
+            Instruction::LOADF { .. } => "loadf",
+            Instruction::FADD { .. } => "fadd",
+            Instruction::FSUB { .. } => "fsub",
+            Instruction::FMUL { .. } => "fmul",
+            Instruction::FDIV { .. } => "fdiv",
+            Instruction::FEQ { .. } => "feq",
+            Instruction::FNEQ { .. } => "fneq",
+            Instruction::FGTE { .. } => "fgte",
+            Instruction::FLTE { .. } => "flte",
+            Instruction::FLT { .. } => "flt",
+            Instruction::FGT { .. } => "fgt",
+            Instruction::ITOF { .. } => "itof",
+            Instruction::FTOI { .. } => "ftoi",
+        }
+    }
+
+    /// Integer registers the instruction writes. `SYSCALL` counts as
+    /// writing `$0`, where host functions return their result.
+    pub fn writes(&self) -> Vec<usize> {
+        match *self {
+            Instruction::INC { r: rd } |
+            Instruction::LOAD { rd, .. } |
+            Instruction::LOADM { rd, .. } |
+            Instruction::LOADBP { rd, .. } |
+            Instruction::POP { rd } |
+            Instruction::ADD { rd, .. } |
+            Instruction::SUB { rd, .. } |
+            Instruction::MUL { rd, .. } |
+            Instruction::DIV { rd, .. } |
+            Instruction::MOD { rd, .. } |
+            Instruction::DIVU { rd, .. } |
+            Instruction::MODU { rd, .. } |
+            Instruction::ADDC { rd, .. } |
+            Instruction::SUBB { rd, .. } |
+            Instruction::AND { rd, .. } |
+            Instruction::OR { rd, .. } |
+            Instruction::XOR { rd, .. } |
+            Instruction::SHL { rd, .. } |
+            Instruction::SHR { rd, .. } |
+            Instruction::SAR { rd, .. } |
+            Instruction::NOT { rd, .. } |
+            Instruction::ANDI { rd, .. } |
+            Instruction::ORI { rd, .. } |
+            Instruction::XORI { rd, .. } |
+            Instruction::SHLI { rd, .. } |
+            Instruction::SHRI { rd, .. } |
+            Instruction::SARI { rd, .. } |
+            Instruction::FTOI { rd, .. } => vec![rd],
+            Instruction::DIVMOD { rq, rr, .. } => vec![rq, rr],
+            Instruction::SYSCALL { .. } => vec![0],
+            _ => vec![],
+        }
+    }
+
+    /// Code offset the instruction may transfer control to.
+    pub fn target(&self) -> Option<usize> {
+        match *self {
+            Instruction::JMP { dst } |
+            Instruction::JMPE { dst } |
+            Instruction::JMPNE { dst } |
+            Instruction::JMPC { dst } |
+            Instruction::JMPO { dst } |
+            Instruction::CALL { dst } |
+            Instruction::LOOP { dst } => Some(dst),
+            _ => None,
+        }
+    }
 }
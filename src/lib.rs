@@ -0,0 +1,8 @@
+pub mod vm;
+pub mod program;
+pub mod bytecode;
+pub mod debugger;
+pub mod assembler;
+pub mod disassembler;
+pub mod instruction;
+pub mod verify;
@@ -0,0 +1,31 @@
+use crate::{
+    assembler::SymbolTable,
+    instruction::Instruction,
+};
+
+/// Output of the assembler: the code, the initial memory image built from
+/// the `.data` section and the symbols both were resolved against.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    pub data: Vec<u8>,
+    pub symbols: SymbolTable,
+}
+
+impl Program {
+    /// Code offsets stored in the jump table of `len` entries at `addr`.
+    /// A table that does not fit in the data image has no entries.
+    pub fn jump_table(&self, addr: usize, len: usize) -> Vec<usize> {
+        let end = len.checked_mul(4)
+            .and_then(|n| n.checked_add(addr))
+            .filter(|end| *end <= self.data.len());
+
+        match end {
+            Some(end) => self.data[addr..end]
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
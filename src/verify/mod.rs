@@ -0,0 +1,318 @@
+//! Static checks on a program before it runs.
+//!
+//! The VM faults instead of panicking on every error found here, but
+//! checking up front reports all of them at once and before any side
+//! effect. Indirect jumps and indirect calls are only known at run time
+//! and are not followed. Jump tables are checked and followed as loaded,
+//! the VM still checks entries the program overwrites.
+
+use std::{collections::BTreeSet, fmt};
+
+use crate::{instruction::Instruction, program::Program};
+
+pub const REGISTERS: usize = 32;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum VerifyError {
+    Empty,
+    RegisterOutOfRange { pc: usize, r: usize },
+    FloatRegisterOutOfRange { pc: usize, r: usize },
+    TargetOutOfRange { pc: usize, dst: usize },
+    TableOutOfRange { pc: usize, table: usize, len: usize },
+    TableEntryOutOfRange { pc: usize, index: usize, dst: usize },
+    FallsOffEnd { pc: usize },
+    RetOutsideCall { pc: usize },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Empty => write!(f, "program has no instructions"),
+            VerifyError::RegisterOutOfRange { pc, r } => write!(f, "pc {}: register ${} does not exist", pc, r),
+            VerifyError::FloatRegisterOutOfRange { pc, r } => write!(f, "pc {}: register $f{} does not exist", pc, r),
+            VerifyError::TargetOutOfRange { pc, dst } => write!(f, "pc {}: target {} is outside of the program", pc, dst),
+            VerifyError::TableOutOfRange { pc, table, len } => {
+                write!(f, "pc {}: jump table of {} entries at {} is outside of the data", pc, len, table)
+            }
+            VerifyError::TableEntryOutOfRange { pc, index, dst } => {
+                write!(f, "pc {}: jump table entry {} targets {}, outside of the program", pc, index, dst)
+            }
+            VerifyError::FallsOffEnd { pc } => write!(f, "pc {}: execution runs past the end of the program", pc),
+            VerifyError::RetOutsideCall { pc } => write!(f, "pc {}: ret is reachable without a call", pc),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Integer and float registers an instruction names.
+fn registers(instruction: &Instruction) -> (Vec<usize>, Vec<usize>) {
+    match *instruction {
+        Instruction::INC { r } |
+        Instruction::CLOOPR { r } |
+        Instruction::JMPR { r } |
+        Instruction::CALLR { r } |
+        Instruction::SWITCH { r, .. } |
+        Instruction::LOAD { rd: r, .. } |
+        Instruction::LOADBP { rd: r, .. } |
+        Instruction::STOREBP { rs: r, .. } |
+        Instruction::PUSH { rs: r } |
+        Instruction::POP { rd: r } => (vec![r], vec![]),
+        Instruction::LOADM { rd, base, .. } => (vec![rd, base], vec![]),
+        Instruction::STOREM { rs, base, .. } => (vec![rs, base], vec![]),
+        Instruction::ADD { rd, rl, rh } |
+        Instruction::SUB { rd, rl, rh } |
+        Instruction::MUL { rd, rl, rh } |
+        Instruction::DIV { rd, rl, rh } |
+        Instruction::MOD { rd, rl, rh } |
+        Instruction::DIVU { rd, rl, rh } |
+        Instruction::MODU { rd, rl, rh } |
+        Instruction::ADDC { rd, rl, rh } |
+        Instruction::SUBB { rd, rl, rh } |
+        Instruction::AND { rd, rl, rh } |
+        Instruction::OR { rd, rl, rh } |
+        Instruction::XOR { rd, rl, rh } |
+        Instruction::SHL { rd, rl, rh } |
+        Instruction::SHR { rd, rl, rh } |
+        Instruction::SAR { rd, rl, rh } => (vec![rd, rl, rh], vec![]),
+        Instruction::DIVMOD { rq, rr, rl, rh } => (vec![rq, rr, rl, rh], vec![]),
+        Instruction::NOT { rd, rs } => (vec![rd, rs], vec![]),
+        Instruction::ANDI { rd, rl, .. } |
+        Instruction::ORI { rd, rl, .. } |
+        Instruction::XORI { rd, rl, .. } |
+        Instruction::SHLI { rd, rl, .. } |
+        Instruction::SHRI { rd, rl, .. } |
+        Instruction::SARI { rd, rl, .. } => (vec![rd, rl], vec![]),
+        Instruction::EQ { rl, rh } |
+        Instruction::NEQ { rl, rh } |
+        Instruction::GTE { rl, rh } |
+        Instruction::LTE { rl, rh } |
+        Instruction::LT { rl, rh } |
+        Instruction::GT { rl, rh } => (vec![rl, rh], vec![]),
+        Instruction::LOADF { fd, .. } => (vec![], vec![fd]),
+        Instruction::FADD { fd, fl, fh } |
+        Instruction::FSUB { fd, fl, fh } |
+        Instruction::FMUL { fd, fl, fh } |
+        Instruction::FDIV { fd, fl, fh } => (vec![], vec![fd, fl, fh]),
+        Instruction::FEQ { fl, fh } |
+        Instruction::FNEQ { fl, fh } |
+        Instruction::FGTE { fl, fh } |
+        Instruction::FLTE { fl, fh } |
+        Instruction::FLT { fl, fh } |
+        Instruction::FGT { fl, fh } => (vec![], vec![fl, fh]),
+        Instruction::ITOF { fd, rs } => (vec![rs], vec![fd]),
+        Instruction::FTOI { rd, fs } => (vec![rd], vec![fs]),
+        Instruction::IGL |
+        Instruction::HLT |
+        Instruction::RET |
+        Instruction::JMP { .. } |
+        Instruction::JMPE { .. } |
+        Instruction::JMPNE { .. } |
+        Instruction::JMPC { .. } |
+        Instruction::JMPO { .. } |
+        Instruction::CALL { .. } |
+        Instruction::CLOOP { .. } |
+        Instruction::LOOP { .. } |
+        Instruction::SYSCALL { .. } => (vec![], vec![]),
+    }
+}
+
+/// Whether the `len` entries of the jump table at `table` lie inside the
+/// data, checked before reading any so a bogus length costs nothing.
+fn table_in_data(program: &Program, table: usize, len: usize) -> bool {
+    len.checked_mul(4)
+        .and_then(|size| size.checked_add(table))
+        .is_some_and(|end| end <= program.data.len())
+}
+
+/// Statically known successors of the instruction at `pc`. With
+/// `into_calls` unset a `CALL` continues at its return site only, and a
+/// `SWITCH` whose table is outside of the data only at its default.
+pub fn successors(program: &Program, instruction: &Instruction, pc: usize, into_calls: bool) -> Vec<usize> {
+    match *instruction {
+        Instruction::HLT | Instruction::IGL | Instruction::RET | Instruction::JMPR { .. } => vec![],
+        Instruction::JMP { dst } => vec![dst],
+        Instruction::SWITCH { table, len, default, .. } if table_in_data(program, table, len) => {
+            program.jump_table(table, len).into_iter().chain(Some(default)).collect()
+        }
+        Instruction::SWITCH { default, .. } => vec![default],
+        Instruction::CALL { dst } if into_calls => vec![dst, pc + 1],
+        Instruction::CALL { .. } => vec![pc + 1],
+        _ => instruction.target().into_iter().chain(Some(pc + 1)).collect(),
+    }
+}
+
+/// Offsets reachable from `entry`, following calls when `into_calls` is
+/// set and only their return site otherwise.
+fn reachable(program: &Program, entry: usize, into_calls: bool) -> BTreeSet<usize> {
+    let mut seen = BTreeSet::new();
+    let mut pending = vec![entry];
+
+    while let Some(pc) = pending.pop() {
+        let instruction = match program.instructions.get(pc) {
+            Some(instruction) if seen.insert(pc) => instruction,
+            _ => continue,
+        };
+
+        pending.extend(successors(program, instruction, pc, into_calls));
+    }
+
+    seen
+}
+
+/// Checks register indices, jump targets and jump tables of every
+/// instruction, and for the code reachable from the entry point that it
+/// neither runs past the last instruction nor returns without a matching
+/// `CALL`.
+pub fn verify(program: &Program) -> Result<(), Vec<VerifyError>> {
+    let instructions = &program.instructions;
+    if instructions.is_empty() {
+        return Err(vec![VerifyError::Empty]);
+    }
+
+    let mut errors = Vec::new();
+
+    for (pc, instruction) in instructions.iter().enumerate() {
+        let (ints, floats) = registers(instruction);
+
+        errors.extend(ints.into_iter()
+            .filter(|r| *r >= REGISTERS)
+            .map(|r| VerifyError::RegisterOutOfRange { pc, r }));
+        errors.extend(floats.into_iter()
+            .filter(|r| *r >= REGISTERS)
+            .map(|r| VerifyError::FloatRegisterOutOfRange { pc, r }));
+
+        let dst = match *instruction {
+            Instruction::SWITCH { default, .. } => Some(default),
+            _ => instruction.target(),
+        };
+        if let Some(dst) = dst.filter(|dst| *dst >= instructions.len()) {
+            errors.push(VerifyError::TargetOutOfRange { pc, dst });
+        }
+
+        if let Instruction::SWITCH { table, len, .. } = *instruction {
+            if !table_in_data(program, table, len) {
+                errors.push(VerifyError::TableOutOfRange { pc, table, len });
+                continue;
+            }
+
+            for (index, dst) in program.jump_table(table, len).into_iter().enumerate() {
+                if dst >= instructions.len() {
+                    errors.push(VerifyError::TableEntryOutOfRange { pc, index, dst });
+                }
+            }
+        }
+    }
+
+    // Only the last instruction can fall through, explicit targets past
+    // the end are reported above already.
+    let last = instructions.len() - 1;
+    if reachable(program, 0, true).contains(&last) && successors(program, &instructions[last], last, true).contains(&(last + 1)) {
+        errors.push(VerifyError::FallsOffEnd { pc: last });
+    }
+
+    for pc in reachable(program, 0, false) {
+        if instructions[pc] == Instruction::RET {
+            errors.push(VerifyError::RetOutsideCall { pc });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::Parser;
+
+    #[test]
+    fn accepts_valid() {
+        let program = Parser::new().process("
+.data
+.jumptable t: @done
+.code
+load $1 &f
+call $1
+call @f
+switch $1 @t @done
+done:
+hlt
+f:
+jmpe @out
+ret
+out:
+ret
+").unwrap();
+
+        assert_eq!(Ok(()), verify(&program));
+    }
+
+    #[test]
+    fn reports_errors() {
+        let program = Program {
+            instructions: vec![
+                Instruction::LOAD { rd: 32, value: 1 },
+                Instruction::FADD { fd: 0, fl: 40, fh: 1 },
+                Instruction::JMPE { dst: 9 },
+                Instruction::CALL { dst: 5 },
+                Instruction::RET,
+                Instruction::INC { r: 0 },
+            ],
+            ..Program::default()
+        };
+
+        assert_eq!(Err(vec![
+            VerifyError::RegisterOutOfRange { pc: 0, r: 32 },
+            VerifyError::FloatRegisterOutOfRange { pc: 1, r: 40 },
+            VerifyError::TargetOutOfRange { pc: 2, dst: 9 },
+            VerifyError::FallsOffEnd { pc: 5 },
+            VerifyError::RetOutsideCall { pc: 4 },
+        ]), verify(&program));
+
+        assert_eq!(Err(vec![VerifyError::Empty]), verify(&Program::default()));
+    }
+
+    #[test]
+    fn jump_tables() {
+        // Entry 1 is the only way to reach the `ret` at 3.
+        let program = Program {
+            instructions: vec![
+                Instruction::SWITCH { r: 0, table: 0, len: 2, default: 2 },
+                Instruction::SWITCH { r: 0, table: 4, len: 2, default: 2 },
+                Instruction::HLT,
+                Instruction::RET,
+            ],
+            data: [2u32, 3, 7].iter().flat_map(|entry| entry.to_le_bytes().to_vec()).collect(),
+            ..Program::default()
+        };
+
+        assert_eq!(Err(vec![
+            VerifyError::TableEntryOutOfRange { pc: 1, index: 1, dst: 7 },
+            VerifyError::RetOutsideCall { pc: 3 },
+        ]), verify(&program));
+
+        let program = Program {
+            instructions: vec![Instruction::SWITCH { r: 0, table: 8, len: 2, default: 1 }, Instruction::HLT],
+            data: vec![0; 12],
+            ..Program::default()
+        };
+
+        assert_eq!(Err(vec![VerifyError::TableOutOfRange { pc: 0, table: 8, len: 2 }]), verify(&program));
+
+        // A length read from a malformed file must not be walked.
+        let len = usize::MAX >> 2;
+        let program = Program {
+            instructions: vec![Instruction::SWITCH { r: 0, table: 0, len, default: 1 }, Instruction::HLT],
+            ..Program::default()
+        };
+        let start = std::time::Instant::now();
+
+        assert_eq!(Err(vec![VerifyError::TableOutOfRange { pc: 0, table: 0, len }]), verify(&program));
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    fmt,
+    str::FromStr,
+};
+
+use super::FaultReason;
+
+/// What `ADD`, `SUB`, `MUL`, `DIV`, `DIVMOD` and `INC` store when the signed result
+/// does not fit into a register. The carry and overflow flags are set the
+/// same way in every mode.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum OverflowMode {
+    #[default]
+    Wrapping,
+    Trapping,
+    Saturating,
+}
+
+impl OverflowMode {
+    /// Narrows the exact result of an operation to a register value.
+    pub fn apply(self, wide: i64) -> Result<i32, FaultReason> {
+        if wide == wide as i32 as i64 {
+            return Ok(wide as i32);
+        }
+
+        match self {
+            OverflowMode::Wrapping => Ok(wide as i32),
+            OverflowMode::Trapping => Err(FaultReason::IntegerOverflow),
+            OverflowMode::Saturating => Ok(wide.clamp(i32::MIN as i64, i32::MAX as i64) as i32),
+        }
+    }
+}
+
+impl FromStr for OverflowMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(OverflowMode::Wrapping),
+            "trap" => Ok(OverflowMode::Trapping),
+            "saturate" => Ok(OverflowMode::Saturating),
+            _ => Err(format!("unknown overflow mode: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for OverflowMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverflowMode::Wrapping => write!(f, "wrap"),
+            OverflowMode::Trapping => write!(f, "trap"),
+            OverflowMode::Saturating => write!(f, "saturate"),
+        }
+    }
+}
+
+/// Exact sum and the unsigned carry out of bit 31.
+pub fn add(l: i32, r: i32, carry: bool) -> (i64, bool) {
+    let unsigned = l as u32 as u64 + r as u32 as u64 + carry as u64;
+
+    (l as i64 + r as i64 + carry as i64, unsigned > u32::MAX as u64)
+}
+
+/// Exact difference and whether the unsigned subtraction borrowed.
+pub fn sub(l: i32, r: i32, borrow: bool) -> (i64, bool) {
+    let borrowed = (l as u32 as u64) < r as u32 as u64 + borrow as u64;
+
+    (l as i64 - r as i64 - borrow as i64, borrowed)
+}
+
+/// Exact product and whether the unsigned product exceeds 32 bits.
+pub fn mul(l: i32, r: i32) -> (i64, bool) {
+    let unsigned = l as u32 as u64 * r as u32 as u64;
+
+    (l as i64 * r as i64, unsigned > u32::MAX as u64)
+}
+
+/// Shifts by `count` taken as unsigned. Counts of 32 and more shift every
+/// bit out: `SHL` and `SHR` give 0, `SAR` gives 0 or -1 by sign.
+pub fn shl(value: i32, count: i32) -> i32 {
+    value.checked_shl(count as u32).unwrap_or(0)
+}
+
+pub fn shr(value: i32, count: i32) -> i32 {
+    (value as u32).checked_shr(count as u32).unwrap_or(0) as i32
+}
+
+pub fn sar(value: i32, count: i32) -> i32 {
+    value >> (count as u32).min(31)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn modes() {
+        let wide = i32::MAX as i64 + 1;
+
+        assert_eq!(Ok(i32::MIN), OverflowMode::Wrapping.apply(wide));
+        assert_eq!(Err(FaultReason::IntegerOverflow), OverflowMode::Trapping.apply(wide));
+        assert_eq!(Ok(i32::MAX), OverflowMode::Saturating.apply(wide));
+        assert_eq!(Ok(i32::MIN), OverflowMode::Saturating.apply(i32::MIN as i64 - 5));
+        assert_eq!(Ok(7), OverflowMode::Trapping.apply(7));
+        assert_eq!(Ok(OverflowMode::Saturating), "saturate".parse());
+    }
+
+    #[test]
+    fn flags() {
+        assert_eq!((0, true), add(-1, 1, false));
+        assert_eq!((i32::MAX as i64 + 1, false), add(i32::MAX, 0, true));
+        assert_eq!((-1, true), sub(0, 1, false));
+        assert_eq!((0, false), sub(1, 0, true));
+        assert_eq!((-2, true), mul(-1, 2));
+        assert_eq!((1 << 32, true), mul(1 << 16, 1 << 16));
+    }
+
+    #[test]
+    fn wide_shifts() {
+        for count in [32, 33, 100, -1, i32::MIN] {
+            assert_eq!(0, shl(-1, count));
+            assert_eq!(0, shr(-1, count));
+            assert_eq!(-1, sar(-1, count));
+            assert_eq!(0, sar(i32::MAX, count));
+        }
+
+        assert_eq!(i32::MIN, shl(1, 31));
+        assert_eq!(1, shr(i32::MIN, 31));
+        assert_eq!(-1, sar(i32::MIN, 31));
+    }
+}
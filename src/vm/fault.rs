@@ -0,0 +1,74 @@
+use std::fmt;
+
+use crate::instruction::Instruction;
+
+/// Why the guest program was stopped.
+#[derive(Debug, PartialEq, Clone)]
+pub enum FaultReason {
+    IllegalInstruction,
+    PcOutOfRange { pc: usize },
+    InvalidJumpTarget { target: i32 },
+    RegisterOutOfRange { r: usize },
+    FloatRegisterOutOfRange { r: usize },
+    MemoryOutOfBounds { addr: i64, len: usize },
+    DivisionByZero,
+    IntegerOverflow,
+    CallStackUnderflow,
+    StackOverflow,
+    StackUnderflow,
+    LoopStackOverflow,
+    UnknownSyscall { n: usize },
+    Host(String),
+}
+
+/// A trap raised by the guest program. `instruction` is `None` when the
+/// fault happened while fetching, i.e. `pc` is past the end of the code.
+#[derive(Debug, PartialEq, Clone)]
+pub struct VmFault {
+    pub pc: usize,
+    pub instruction: Option<Instruction>,
+    pub reason: FaultReason,
+}
+
+/// How a guest program stopped without faulting. `HLT` exits with the
+/// value of `$0` as status code, `Yielded` means the budget given to
+/// `VM::run_for` ran out and the program can be resumed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExitStatus {
+    Halted(i32),
+    Yielded,
+}
+
+impl fmt::Display for FaultReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultReason::IllegalInstruction => write!(f, "illegal instruction"),
+            FaultReason::PcOutOfRange { pc } => write!(f, "pc {} is outside of the program", pc),
+            FaultReason::InvalidJumpTarget { target } => write!(f, "jump target {} is outside of the program", target),
+            FaultReason::RegisterOutOfRange { r } => write!(f, "register ${} does not exist", r),
+            FaultReason::FloatRegisterOutOfRange { r } => write!(f, "register $f{} does not exist", r),
+            FaultReason::MemoryOutOfBounds { addr, len } => {
+                write!(f, "memory access of {} bytes at {} is out of bounds", len, addr)
+            }
+            FaultReason::DivisionByZero => write!(f, "division by zero"),
+            FaultReason::IntegerOverflow => write!(f, "integer overflow"),
+            FaultReason::CallStackUnderflow => write!(f, "ret with an empty call stack"),
+            FaultReason::StackOverflow => write!(f, "stack overflow"),
+            FaultReason::StackUnderflow => write!(f, "pop from an empty stack"),
+            FaultReason::LoopStackOverflow => write!(f, "too many nested loops"),
+            FaultReason::UnknownSyscall { n } => write!(f, "no host function registered for syscall {}", n),
+            FaultReason::Host(message) => write!(f, "host function failed: {}", message),
+        }
+    }
+}
+
+impl fmt::Display for VmFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.instruction {
+            Some(instruction) => write!(f, "fault at pc {} ({:?}): {}", self.pc, instruction, self.reason),
+            None => write!(f, "fault at pc {}: {}", self.pc, self.reason),
+        }
+    }
+}
+
+impl std::error::Error for VmFault {}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use crate::instruction::Width;
+
+use super::{FaultReason, Memory};
+
+pub type Handler = Box<dyn FnMut(&mut Host) -> Result<(), FaultReason>>;
+
+/// View of the guest state handed to a syscall handler.
+///
+/// By convention arguments are passed in `$1`, `$2`, ... and the result
+/// is returned in `$0`.
+pub struct Host<'a> {
+    pub ir: &'a mut [i32; 32],
+    pub memory: &'a mut Memory,
+}
+
+impl Host<'_> {
+    /// Argument `n`, held in `$n+1`. Only `n < 31` names a register.
+    pub fn arg(&self, n: usize) -> Result<i32, FaultReason> {
+        let r = n.saturating_add(1);
+
+        self.ir.get(r).copied().ok_or(FaultReason::RegisterOutOfRange { r })
+    }
+
+    pub fn ret(&mut self, value: i32) {
+        self.ir[0] = value;
+    }
+
+    pub fn read(&self, addr: i32, len: usize) -> Result<Vec<u8>, FaultReason> {
+        (0..len)
+            .map(|i| self.byte(addr as i64 + i as i64))
+            .collect()
+    }
+
+    /// Reads a NUL-terminated string starting at `addr`.
+    pub fn read_cstr(&self, addr: i32) -> Result<Vec<u8>, FaultReason> {
+        let mut bytes = Vec::new();
+
+        loop {
+            match self.byte(addr as i64 + bytes.len() as i64)? {
+                0 => return Ok(bytes),
+                b => bytes.push(b),
+            }
+        }
+    }
+
+    pub fn write(&mut self, addr: i32, bytes: &[u8]) -> Result<(), FaultReason> {
+        for (i, b) in bytes.iter().enumerate() {
+            let at = self.address(addr as i64 + i as i64, 1)?;
+
+            self.memory.store(at, Width::Byte, *b as i32)?;
+        }
+
+        Ok(())
+    }
+
+    fn byte(&self, addr: i64) -> Result<u8, FaultReason> {
+        let at = self.address(addr, 1)?;
+
+        Ok(self.memory.load(at, Width::Byte)? as u8)
+    }
+
+    fn address(&self, addr: i64, len: usize) -> Result<usize, FaultReason> {
+        if addr < 0 {
+            return Err(FaultReason::MemoryOutOfBounds { addr, len });
+        }
+
+        Ok(addr as usize)
+    }
+}
+
+struct Syscall {
+    name: String,
+    handler: Handler,
+}
+
+/// Host functions callable from the guest with `SYSCALL n`.
+#[derive(Default)]
+pub struct Syscalls(HashMap<usize, Syscall>);
+
+impl Syscalls {
+    pub fn register(&mut self, n: usize, name: &str, handler: Handler) -> Option<String> {
+        self.0.insert(n, Syscall { name: name.to_string(), handler })
+            .map(|previous| previous.name)
+    }
+
+    pub fn number(&self, name: &str) -> Option<usize> {
+        self.0.iter()
+            .find(|(_, syscall)| syscall.name == name)
+            .map(|(n, _)| *n)
+    }
+
+    pub fn name(&self, n: usize) -> Option<&str> {
+        self.0.get(&n).map(|syscall| syscall.name.as_str())
+    }
+
+    pub fn call(&mut self, n: usize, host: &mut Host) -> Result<(), FaultReason> {
+        match self.0.get_mut(&n) {
+            Some(syscall) => (syscall.handler)(host),
+            None => Err(FaultReason::UnknownSyscall { n }),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::instruction::Width;
+
+use super::FaultReason;
+
+/// Byte-addressable linear memory. Multi-byte values are little-endian,
+/// narrow loads are zero-extended.
+#[derive(Debug, Clone)]
+pub struct Memory(Vec<u8>);
+
+impl Memory {
+    pub fn new(size: usize) -> Self {
+        Self(vec![0; size])
+    }
+
+    /// Memory of at least `size` bytes with `image` copied to address 0.
+    pub fn with_image(image: &[u8], size: usize) -> Self {
+        let mut bytes = image.to_vec();
+        bytes.resize(size.max(image.len()), 0);
+
+        Self(bytes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn load(&self, addr: usize, width: Width) -> Result<i32, FaultReason> {
+        let bytes = self.slice(addr, width.bytes())?;
+
+        Ok(match width {
+            Width::Byte => bytes[0] as i32,
+            Width::Half => u16::from_le_bytes([bytes[0], bytes[1]]) as i32,
+            Width::Word => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        })
+    }
+
+    pub fn store(&mut self, addr: usize, width: Width, value: i32) -> Result<(), FaultReason> {
+        let len = width.bytes();
+        let bytes = value.to_le_bytes();
+
+        self.slice_mut(addr, len)?.copy_from_slice(&bytes[..len]);
+
+        Ok(())
+    }
+
+    fn slice(&self, addr: usize, len: usize) -> Result<&[u8], FaultReason> {
+        let end = addr.checked_add(len).filter(|end| *end <= self.0.len());
+
+        match end {
+            Some(end) => Ok(&self.0[addr..end]),
+            None => Err(FaultReason::MemoryOutOfBounds { addr: addr as i64, len }),
+        }
+    }
+
+    fn slice_mut(&mut self, addr: usize, len: usize) -> Result<&mut [u8], FaultReason> {
+        let end = addr.checked_add(len).filter(|end| *end <= self.0.len());
+
+        match end {
+            Some(end) => Ok(&mut self.0[addr..end]),
+            None => Err(FaultReason::MemoryOutOfBounds { addr: addr as i64, len }),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn store_load() {
+        let mut memory = Memory::new(8);
+
+        memory.store(0, Width::Word, -2).unwrap();
+        assert_eq!(Ok(-2), memory.load(0, Width::Word));
+        assert_eq!(Ok(0xFE), memory.load(0, Width::Byte));
+        assert_eq!(Ok(0xFFFF), memory.load(2, Width::Half));
+
+        memory.store(4, Width::Byte, 0x1234).unwrap();
+        assert_eq!(Ok(0x34), memory.load(4, Width::Word));
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let mut memory = Memory::new(8);
+
+        assert_eq!(Err(FaultReason::MemoryOutOfBounds { addr: 6, len: 4 }), memory.load(6, Width::Word));
+        assert_eq!(Err(FaultReason::MemoryOutOfBounds { addr: 8, len: 1 }), memory.store(8, Width::Byte, 0));
+        assert!(memory.load(usize::MAX, Width::Half).is_err());
+        assert_eq!(Ok(0), memory.load(7, Width::Byte));
+    }
+
+    #[test]
+    fn image() {
+        let memory = Memory::with_image(&[1, 2, 3], 8);
+        assert_eq!(8, memory.len());
+        assert_eq!(Ok(0x030201), memory.load(0, Width::Word));
+
+        let memory = Memory::with_image(&[1, 2, 3], 2);
+        assert_eq!(3, memory.len());
+    }
+}
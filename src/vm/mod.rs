@@ -0,0 +1,1041 @@
+mod arith;
+mod fault;
+mod host;
+mod memory;
+
+pub use arith::OverflowMode;
+pub use fault::{ExitStatus, FaultReason, VmFault};
+pub use host::Host;
+pub use memory::Memory;
+
+use arith::{shl, shr, sar};
+use host::Syscalls;
+
+use crate::instruction::{Instruction, Width};
+
+pub const DEFAULT_MEMORY_SIZE: usize = 64 * 1024;
+pub const DEFAULT_STACK_SIZE: usize = 16 * 1024;
+pub const DEFAULT_CALL_DEPTH: usize = 1024;
+pub const DEFAULT_LOOP_DEPTH: usize = 64;
+
+/// Price of an instruction in budget units, see `VM::run_for`.
+pub type CostTable = Box<dyn Fn(&Instruction) -> u64>;
+
+/// Every instruction costs one unit, so a budget counts instructions.
+pub fn unit_cost(_: &Instruction) -> u64 {
+    1
+}
+
+enum Step {
+    Halt,
+    PCNext,
+    PCSet(usize),
+}
+
+/// The data stack occupies the last `stack_size` bytes of memory and grows
+/// down from the end, `sp` points at the last pushed word. `CALL` starts a
+/// frame at the current `sp`, so `[bp+0]` is the last word the caller
+/// pushed and callee locals live below `bp`. Return addresses are kept
+/// apart on `stack`, at most `max_call_depth` frames deep.
+///
+/// `CLOOP` pushes a counter onto `loops`, at most `max_loop_depth` deep,
+/// and `LOOP` works on the innermost one, popping it once it is exhausted.
+/// Nested loops therefore each keep their own count.
+pub struct VM {
+    pub ir: [i32; 32],
+    pub fr: [f32; 32],
+    pub pc: usize,
+    pub sp: usize,
+    pub bp: usize,
+    pub stack_size: usize,
+    pub max_call_depth: usize,
+    pub max_loop_depth: usize,
+    pub running: bool,
+    pub remainder: i32,
+    pub compare_flag: bool,
+    pub carry_flag: bool,
+    pub overflow_flag: bool,
+    pub overflow_mode: OverflowMode,
+    pub loops: Vec<usize>,
+    pub stack: Vec<usize>,
+    pub memory: Memory,
+    pub instructions: Vec<Instruction>,
+    syscalls: Syscalls,
+    cost: CostTable,
+}
+
+impl VM {
+    pub fn new(instructions: Vec<Instruction>, data: &[u8]) -> Self {
+        let memory = Memory::with_image(data, DEFAULT_MEMORY_SIZE);
+
+        Self {
+            instructions,
+            pc: 0,
+            sp: memory.len(),
+            bp: memory.len(),
+            stack_size: DEFAULT_STACK_SIZE,
+            max_call_depth: DEFAULT_CALL_DEPTH,
+            max_loop_depth: DEFAULT_LOOP_DEPTH,
+            ir: [0; 32],
+            fr: [0.0; 32],
+            stack: Vec::new(),
+            memory,
+            running: true,
+            remainder: 0,
+            loops: Vec::new(),
+            compare_flag: false,
+            carry_flag: false,
+            overflow_flag: false,
+            overflow_mode: OverflowMode::default(),
+            syscalls: Syscalls::default(),
+            cost: Box::new(unit_cost),
+        }
+    }
+
+    /// Installs the host function invoked by `SYSCALL n`, replacing any
+    /// previous one registered under `n`.
+    pub fn register_syscall<F>(&mut self, n: usize, name: &str, handler: F) -> Option<String>
+        where F: FnMut(&mut Host) -> Result<(), FaultReason> + 'static
+    {
+        self.syscalls.register(n, name, Box::new(handler))
+    }
+
+    pub fn syscall_number(&self, name: &str) -> Option<usize> {
+        self.syscalls.number(name)
+    }
+
+    pub fn syscall_name(&self, n: usize) -> Option<&str> {
+        self.syscalls.name(n)
+    }
+
+    /// Runs until the program halts or faults. A fault never panics the
+    /// host: the VM stops at the faulting pc and `running` is cleared.
+    pub fn run(&mut self) -> Result<ExitStatus, VmFault> {
+        loop {
+            if let Some(status) = self.step()? {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Like `run`, but executes instructions only while their summed cost
+    /// fits into `budget`. Returns `ExitStatus::Yielded` when the next
+    /// instruction would exceed it; the VM state is left untouched so the
+    /// caller can resume with another call.
+    pub fn run_for(&mut self, budget: u64) -> Result<ExitStatus, VmFault> {
+        let mut remaining = budget;
+
+        loop {
+            if let Some(instruction) = self.instructions.get(self.pc) {
+                let cost = (self.cost)(instruction);
+                if cost > remaining {
+                    return Ok(ExitStatus::Yielded);
+                }
+
+                remaining -= cost;
+            }
+
+            if let Some(status) = self.step()? {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Replaces the cost table used by `run_for`, `unit_cost` by default.
+    pub fn set_cost_table<F>(&mut self, cost: F)
+        where F: Fn(&Instruction) -> u64 + 'static
+    {
+        self.cost = Box::new(cost);
+    }
+
+    /// Executes a single instruction, returning the exit status once the
+    /// program halts.
+    pub fn step(&mut self) -> Result<Option<ExitStatus>, VmFault> {
+        let instruction = match self.instructions.get(self.pc) {
+            Some(instruction) => *instruction,
+            None => {
+                self.running = false;
+
+                return Err(VmFault { pc: self.pc, instruction: None, reason: FaultReason::PcOutOfRange { pc: self.pc } });
+            }
+        };
+
+        match self.execute_instruction(instruction) {
+            Ok(Step::Halt) => {
+                self.running = false;
+
+                Ok(Some(ExitStatus::Halted(self.ir[0])))
+            }
+            Ok(Step::PCNext) => {
+                self.pc += 1;
+
+                Ok(None)
+            }
+            Ok(Step::PCSet(pc)) => {
+                self.pc = pc;
+
+                Ok(None)
+            }
+            Err(reason) => {
+                self.running = false;
+
+                Err(VmFault { pc: self.pc, instruction: Some(instruction), reason })
+            }
+        }
+    }
+
+    #[inline]
+    fn reg(&self, r: usize) -> Result<i32, FaultReason> {
+        self.ir.get(r).copied().ok_or(FaultReason::RegisterOutOfRange { r })
+    }
+
+    #[inline]
+    fn set_reg(&mut self, r: usize, value: i32) -> Result<(), FaultReason> {
+        let slot = self.ir.get_mut(r).ok_or(FaultReason::RegisterOutOfRange { r })?;
+        *slot = value;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn freg(&self, r: usize) -> Result<f32, FaultReason> {
+        self.fr.get(r).copied().ok_or(FaultReason::FloatRegisterOutOfRange { r })
+    }
+
+    #[inline]
+    fn set_freg(&mut self, r: usize, value: f32) -> Result<(), FaultReason> {
+        let slot = self.fr.get_mut(r).ok_or(FaultReason::FloatRegisterOutOfRange { r })?;
+        *slot = value;
+
+        Ok(())
+    }
+
+    /// Sets the carry and overflow flags from an exact result and stores
+    /// it narrowed by `mode`.
+    fn set_arith(&mut self, rd: usize, (wide, carry): (i64, bool), mode: OverflowMode) -> Result<(), FaultReason> {
+        self.carry_flag = carry;
+        self.overflow_flag = wide != wide as i32 as i64;
+
+        self.set_reg(rd, mode.apply(wide)?)
+    }
+
+    /// Operands of a division, faulting on a zero divisor.
+    fn divisor(&self, rl: usize, rh: usize) -> Result<(i32, i32), FaultReason> {
+        let (l, h) = (self.reg(rl)?, self.reg(rh)?);
+        if h == 0 {
+            return Err(FaultReason::DivisionByZero);
+        }
+
+        Ok((l, h))
+    }
+
+    fn push_loop(&mut self, count: usize) -> Result<(), FaultReason> {
+        if self.loops.len() >= self.max_loop_depth {
+            return Err(FaultReason::LoopStackOverflow);
+        }
+
+        self.loops.push(count);
+
+        Ok(())
+    }
+
+    fn call(&mut self, dst: usize) -> Result<Step, FaultReason> {
+        if self.stack.len() / 2 >= self.max_call_depth {
+            return Err(FaultReason::StackOverflow);
+        }
+
+        self.stack.push(self.pc + 1);
+        self.stack.push(self.bp);
+        self.bp = self.sp;
+
+        Ok(Step::PCSet(dst))
+    }
+
+    /// Code offset held in register `r`, checked so an indirect jump faults
+    /// at the jump rather than at the next fetch.
+    fn jump_target(&self, r: usize) -> Result<usize, FaultReason> {
+        let target = self.reg(r)?;
+
+        if target < 0 || target as usize >= self.instructions.len() {
+            return Err(FaultReason::InvalidJumpTarget { target });
+        }
+
+        Ok(target as usize)
+    }
+
+    fn frame_address(&self, offset: i32, width: Width) -> Result<usize, FaultReason> {
+        let addr = self.bp as i64 + offset as i64;
+
+        if addr < 0 {
+            return Err(FaultReason::MemoryOutOfBounds { addr, len: width.bytes() });
+        }
+
+        Ok(addr as usize)
+    }
+
+    fn effective_address(&self, base: usize, offset: i32, width: Width) -> Result<usize, FaultReason> {
+        let addr = self.reg(base)? as i64 + offset as i64;
+
+        if addr < 0 {
+            return Err(FaultReason::MemoryOutOfBounds { addr, len: width.bytes() });
+        }
+
+        Ok(addr as usize)
+    }
+
+    #[inline]
+    fn execute_instruction(&mut self, instruction: Instruction) -> Result<Step, FaultReason> {
+        match instruction {
+            Instruction::IGL => {
+                return Err(FaultReason::IllegalInstruction);
+            }
+            Instruction::HLT => {
+                return Ok(Step::Halt);
+            }
+            Instruction::JMP { dst: r } => {
+                return Ok(Step::PCSet(r));
+            }
+            Instruction::JMPE { dst: r } => {
+                if self.compare_flag {
+                    return Ok(Step::PCSet(r));
+                }
+            }
+            Instruction::JMPNE { dst: r } => {
+                if !self.compare_flag {
+                    return Ok(Step::PCSet(r));
+                }
+            }
+            Instruction::JMPC { dst: r } => {
+                if self.carry_flag {
+                    return Ok(Step::PCSet(r));
+                }
+            }
+            Instruction::JMPO { dst: r } => {
+                if self.overflow_flag {
+                    return Ok(Step::PCSet(r));
+                }
+            }
+            Instruction::LOAD { rd, value } => {
+                self.set_reg(rd, value)?;
+            }
+            Instruction::LOADM { width, rd, base, offset } => {
+                let addr = self.effective_address(base, offset, width)?;
+                let value = self.memory.load(addr, width)?;
+
+                self.set_reg(rd, value)?;
+            }
+            Instruction::STOREM { width, rs, base, offset } => {
+                let addr = self.effective_address(base, offset, width)?;
+                let value = self.reg(rs)?;
+
+                self.memory.store(addr, width, value)?;
+            }
+            Instruction::LOADBP { width, rd, offset } => {
+                let addr = self.frame_address(offset, width)?;
+                let value = self.memory.load(addr, width)?;
+
+                self.set_reg(rd, value)?;
+            }
+            Instruction::STOREBP { width, rs, offset } => {
+                let addr = self.frame_address(offset, width)?;
+                let value = self.reg(rs)?;
+
+                self.memory.store(addr, width, value)?;
+            }
+            Instruction::PUSH { rs } => {
+                let limit = self.memory.len().saturating_sub(self.stack_size);
+                if self.sp < limit + 4 {
+                    return Err(FaultReason::StackOverflow);
+                }
+
+                self.memory.store(self.sp - 4, Width::Word, self.reg(rs)?)?;
+                self.sp -= 4;
+            }
+            Instruction::POP { rd } => {
+                if self.sp + 4 > self.memory.len() {
+                    return Err(FaultReason::StackUnderflow);
+                }
+
+                self.set_reg(rd, self.memory.load(self.sp, Width::Word)?)?;
+                self.sp += 4;
+            }
+            Instruction::RET => {
+                if self.stack.len() < 2 {
+                    return Err(FaultReason::CallStackUnderflow);
+                }
+
+                self.sp = self.bp;
+                self.bp = self.stack.pop().unwrap();
+
+                return Ok(Step::PCSet(self.stack.pop().unwrap()));
+            }
+            Instruction::CALL { dst } => {
+                return self.call(dst);
+            }
+            Instruction::JMPR { r } => {
+                return Ok(Step::PCSet(self.jump_target(r)?));
+            }
+            Instruction::CALLR { r } => {
+                let dst = self.jump_target(r)?;
+
+                return self.call(dst);
+            }
+            Instruction::SWITCH { r, table, len, default } => {
+                let index = self.reg(r)?;
+                if index < 0 || index as usize >= len {
+                    return Ok(Step::PCSet(default));
+                }
+
+                // The table lives in writable memory, so entries are checked
+                // like any other indirect jump target.
+                let addr = (index as usize).checked_mul(4)
+                    .and_then(|offset| table.checked_add(offset))
+                    .ok_or(FaultReason::MemoryOutOfBounds { addr: table as i64, len: len.saturating_mul(4) })?;
+                let target = self.memory.load(addr, Width::Word)?;
+                if target < 0 || target as usize >= self.instructions.len() {
+                    return Err(FaultReason::InvalidJumpTarget { target });
+                }
+
+                return Ok(Step::PCSet(target as usize));
+            }
+            Instruction::LOOP { dst: r } => {
+                match self.loops.last_mut() {
+                    Some(0) => {
+                        self.loops.pop();
+                    }
+                    Some(counter) => {
+                        *counter -= 1;
+                        return Ok(Step::PCSet(r));
+                    }
+                    None => {}
+                }
+            }
+            Instruction::CLOOP { count } => {
+                self.push_loop(count)?;
+            }
+            Instruction::CLOOPR { r } => {
+                // A negative count behaves like zero.
+                let count = self.reg(r)?.max(0) as usize;
+
+                self.push_loop(count)?;
+            }
+            Instruction::SYSCALL { n } => {
+                let mut host = Host { ir: &mut self.ir, memory: &mut self.memory };
+
+                self.syscalls.call(n, &mut host)?;
+            }
+            Instruction::INC { r } => {
+                self.set_arith(r, arith::add(self.reg(r)?, 1, false), self.overflow_mode)?;
+            }
+            Instruction::ADD { rd, rl, rh } => {
+                self.set_arith(rd, arith::add(self.reg(rl)?, self.reg(rh)?, false), self.overflow_mode)?;
+            }
+            Instruction::SUB { rd, rl, rh } => {
+                self.set_arith(rd, arith::sub(self.reg(rl)?, self.reg(rh)?, false), self.overflow_mode)?;
+            }
+            Instruction::MUL { rd, rl, rh } => {
+                self.set_arith(rd, arith::mul(self.reg(rl)?, self.reg(rh)?), self.overflow_mode)?;
+            }
+            Instruction::DIV { rd, rl, rh } => {
+                let (l, h) = self.divisor(rl, rh)?;
+
+                // Only `i32::MIN / -1` overflows, its remainder is 0.
+                self.set_arith(rd, (l as i64 / h as i64, false), self.overflow_mode)?;
+                self.remainder = l.wrapping_rem(h);
+            }
+            Instruction::MOD { rd, rl, rh } => {
+                let (l, h) = self.divisor(rl, rh)?;
+
+                self.remainder = l.wrapping_rem(h);
+                self.set_reg(rd, self.remainder)?;
+            }
+            Instruction::DIVMOD { rq, rr, rl, rh } => {
+                let (l, h) = self.divisor(rl, rh)?;
+
+                self.set_arith(rq, (l as i64 / h as i64, false), self.overflow_mode)?;
+                self.remainder = l.wrapping_rem(h);
+                self.set_reg(rr, self.remainder)?;
+            }
+            // The unsigned forms read both operands as `u32` and never overflow.
+            Instruction::DIVU { rd, rl, rh } => {
+                let (l, h) = self.divisor(rl, rh)?;
+
+                self.remainder = (l as u32 % h as u32) as i32;
+                self.set_reg(rd, (l as u32 / h as u32) as i32)?;
+            }
+            Instruction::MODU { rd, rl, rh } => {
+                let (l, h) = self.divisor(rl, rh)?;
+
+                self.remainder = (l as u32 % h as u32) as i32;
+                self.set_reg(rd, self.remainder)?;
+            }
+            // The carry-in forms are the upper words of multi-word arithmetic
+            // and always wrap, whatever the overflow mode.
+            Instruction::ADDC { rd, rl, rh } => {
+                let result = arith::add(self.reg(rl)?, self.reg(rh)?, self.carry_flag);
+
+                self.set_arith(rd, result, OverflowMode::Wrapping)?;
+            }
+            Instruction::SUBB { rd, rl, rh } => {
+                let result = arith::sub(self.reg(rl)?, self.reg(rh)?, self.carry_flag);
+
+                self.set_arith(rd, result, OverflowMode::Wrapping)?;
+            }
+            Instruction::AND { rd, rl, rh } => {
+                self.set_reg(rd, self.reg(rl)? & self.reg(rh)?)?;
+            }
+            Instruction::OR { rd, rl, rh } => {
+                self.set_reg(rd, self.reg(rl)? | self.reg(rh)?)?;
+            }
+            Instruction::XOR { rd, rl, rh } => {
+                self.set_reg(rd, self.reg(rl)? ^ self.reg(rh)?)?;
+            }
+            Instruction::SHL { rd, rl, rh } => {
+                self.set_reg(rd, shl(self.reg(rl)?, self.reg(rh)?))?;
+            }
+            Instruction::SHR { rd, rl, rh } => {
+                self.set_reg(rd, shr(self.reg(rl)?, self.reg(rh)?))?;
+            }
+            Instruction::SAR { rd, rl, rh } => {
+                self.set_reg(rd, sar(self.reg(rl)?, self.reg(rh)?))?;
+            }
+            Instruction::NOT { rd, rs } => {
+                self.set_reg(rd, !self.reg(rs)?)?;
+            }
+            Instruction::ANDI { rd, rl, value } => {
+                self.set_reg(rd, self.reg(rl)? & value)?;
+            }
+            Instruction::ORI { rd, rl, value } => {
+                self.set_reg(rd, self.reg(rl)? | value)?;
+            }
+            Instruction::XORI { rd, rl, value } => {
+                self.set_reg(rd, self.reg(rl)? ^ value)?;
+            }
+            Instruction::SHLI { rd, rl, value } => {
+                self.set_reg(rd, shl(self.reg(rl)?, value))?;
+            }
+            Instruction::SHRI { rd, rl, value } => {
+                self.set_reg(rd, shr(self.reg(rl)?, value))?;
+            }
+            Instruction::SARI { rd, rl, value } => {
+                self.set_reg(rd, sar(self.reg(rl)?, value))?;
+            }
+            Instruction::EQ { rl, rh } => {
+                self.compare_flag = self.reg(rl)? == self.reg(rh)?;
+            }
+            Instruction::NEQ { rl, rh } => {
+                self.compare_flag = self.reg(rl)? != self.reg(rh)?;
+            }
+            Instruction::GTE { rl, rh } => {
+                self.compare_flag = self.reg(rl)? >= self.reg(rh)?;
+            }
+            Instruction::LTE { rl, rh } => {
+                self.compare_flag = self.reg(rl)? <= self.reg(rh)?;
+            }
+            Instruction::LT { rl, rh } => {
+                self.compare_flag = self.reg(rl)? < self.reg(rh)?;
+            }
+            Instruction::GT { rl, rh } => {
+                self.compare_flag = self.reg(rl)? > self.reg(rh)?;
+            }
+            Instruction::LOADF { fd, value } => {
+                self.set_freg(fd, value)?;
+            }
+            // Float arithmetic follows IEEE 754, dividing by zero gives an
+            // infinity or NaN rather than a fault.
+            Instruction::FADD { fd, fl, fh } => {
+                self.set_freg(fd, self.freg(fl)? + self.freg(fh)?)?;
+            }
+            Instruction::FSUB { fd, fl, fh } => {
+                self.set_freg(fd, self.freg(fl)? - self.freg(fh)?)?;
+            }
+            Instruction::FMUL { fd, fl, fh } => {
+                self.set_freg(fd, self.freg(fl)? * self.freg(fh)?)?;
+            }
+            Instruction::FDIV { fd, fl, fh } => {
+                self.set_freg(fd, self.freg(fl)? / self.freg(fh)?)?;
+            }
+            Instruction::FEQ { fl, fh } => {
+                self.compare_flag = self.freg(fl)? == self.freg(fh)?;
+            }
+            Instruction::FNEQ { fl, fh } => {
+                self.compare_flag = self.freg(fl)? != self.freg(fh)?;
+            }
+            Instruction::FGTE { fl, fh } => {
+                self.compare_flag = self.freg(fl)? >= self.freg(fh)?;
+            }
+            Instruction::FLTE { fl, fh } => {
+                self.compare_flag = self.freg(fl)? <= self.freg(fh)?;
+            }
+            Instruction::FLT { fl, fh } => {
+                self.compare_flag = self.freg(fl)? < self.freg(fh)?;
+            }
+            Instruction::FGT { fl, fh } => {
+                self.compare_flag = self.freg(fl)? > self.freg(fh)?;
+            }
+            Instruction::ITOF { fd, rs } => {
+                self.set_freg(fd, self.reg(rs)? as f32)?;
+            }
+            // Truncates toward zero, saturating at the `i32` bounds; NaN becomes 0.
+            Instruction::FTOI { rd, fs } => {
+                self.set_reg(rd, self.freg(fs)? as i32)?;
+            }
+        }
+
+        Ok(Step::PCNext)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn memory_roundtrip() {
+        let mut vm = VM::new(vec![
+            Instruction::LOAD { rd: 0, value: 16 },
+            Instruction::LOAD { rd: 1, value: 0x0102_0304 },
+            Instruction::STOREM { width: Width::Word, rs: 1, base: 0, offset: 4 },
+            Instruction::LOADM { width: Width::Byte, rd: 2, base: 0, offset: 4 },
+            Instruction::LOADM { width: Width::Half, rd: 3, base: 0, offset: 6 },
+            Instruction::LOADM { width: Width::Word, rd: 4, base: 0, offset: 4 },
+            Instruction::HLT,
+        ], &[]);
+
+        assert_eq!(Ok(ExitStatus::Halted(16)), vm.run());
+        assert_eq!(0x04, vm.ir[2]);
+        assert_eq!(0x0102, vm.ir[3]);
+        assert_eq!(0x0102_0304, vm.ir[4]);
+    }
+
+    #[test]
+    fn memory_out_of_bounds() {
+        let mut vm = VM::new(vec![
+            Instruction::LOAD { rd: 0, value: DEFAULT_MEMORY_SIZE as i32 - 2 },
+            Instruction::LOADM { width: Width::Word, rd: 1, base: 0, offset: 0 },
+            Instruction::HLT,
+        ], &[]);
+
+        assert_eq!(
+            FaultReason::MemoryOutOfBounds { addr: DEFAULT_MEMORY_SIZE as i64 - 2, len: 4 },
+            vm.run().unwrap_err().reason,
+        );
+
+        let mut vm = VM::new(vec![
+            Instruction::STOREM { width: Width::Byte, rs: 1, base: 0, offset: -1 },
+            Instruction::HLT,
+        ], &[]);
+
+        assert_eq!(FaultReason::MemoryOutOfBounds { addr: -1, len: 1 }, vm.run().unwrap_err().reason);
+    }
+
+    #[test]
+    fn walk_data_string() {
+        let program = crate::assembler::Parser::new().process("
+.data
+msg: .asciiz 'hello world'
+.code
+load $0 @msg
+load $1 #0
+load $3 #0
+next:
+loadb $2 [$0]
+eq $2 $3
+jmpe @done
+inc $0
+inc $1
+jmp @next
+done:
+hlt
+").unwrap();
+
+        let mut vm = VM::new(program.instructions, &program.data);
+
+        assert_eq!(Ok(ExitStatus::Halted(11)), vm.run());
+        assert_eq!(11, vm.ir[1]);
+    }
+
+    #[test]
+    fn halt_status() {
+        let mut vm = VM::new(vec![
+            Instruction::LOAD { rd: 0, value: 3 },
+            Instruction::HLT,
+        ], &[]);
+
+        assert_eq!(Ok(ExitStatus::Halted(3)), vm.run());
+        assert!(!vm.running);
+    }
+
+    #[test]
+    fn faults() {
+        let cases = vec![
+            (vec![Instruction::DIV { rd: 0, rl: 1, rh: 2 }], 0, FaultReason::DivisionByZero),
+            (vec![Instruction::MOD { rd: 0, rl: 1, rh: 2 }], 0, FaultReason::DivisionByZero),
+            (vec![Instruction::DIVMOD { rq: 0, rr: 3, rl: 1, rh: 2 }], 0, FaultReason::DivisionByZero),
+            (vec![Instruction::DIVU { rd: 0, rl: 1, rh: 2 }], 0, FaultReason::DivisionByZero),
+            (vec![Instruction::MODU { rd: 0, rl: 1, rh: 2 }], 0, FaultReason::DivisionByZero),
+            (vec![Instruction::RET], 0, FaultReason::CallStackUnderflow),
+            (vec![Instruction::INC { r: 32 }], 0, FaultReason::RegisterOutOfRange { r: 32 }),
+            (vec![Instruction::LOAD { rd: 0, value: 1 }, Instruction::IGL], 1, FaultReason::IllegalInstruction),
+            (vec![Instruction::SYSCALL { n: 7 }], 0, FaultReason::UnknownSyscall { n: 7 }),
+        ];
+
+        for (instructions, pc, reason) in cases {
+            let instruction = instructions[pc];
+            let mut vm = VM::new(instructions, &[]);
+
+            assert_eq!(Err(VmFault { pc, instruction: Some(instruction), reason }), vm.run());
+            assert!(!vm.running);
+        }
+    }
+
+    #[test]
+    fn run_off_the_end() {
+        let mut vm = VM::new(vec![Instruction::INC { r: 0 }, Instruction::JMP { dst: 5 }], &[]);
+
+        assert_eq!(
+            Err(VmFault { pc: 5, instruction: None, reason: FaultReason::PcOutOfRange { pc: 5 } }),
+            vm.run(),
+        );
+        assert_eq!(1, vm.ir[0]);
+    }
+
+    #[test]
+    fn syscalls() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let program = crate::assembler::Parser::new().process("
+.data
+name: .asciiz 'svm'
+.code
+load $1 &name
+syscall #2
+load $1 #40
+syscall #1
+hlt
+").unwrap();
+        let mut vm = VM::new(program.instructions, &program.data);
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let sink = log.clone();
+        vm.register_syscall(2, "log", move |host: &mut Host| {
+            let bytes = host.read_cstr(host.arg(0)?)?;
+            sink.borrow_mut().push(String::from_utf8_lossy(&bytes).into_owned());
+
+            Ok(())
+        });
+        vm.register_syscall(1, "answer", |host: &mut Host| {
+            host.ret(host.arg(0)? + 2);
+
+            Ok(())
+        });
+
+        assert_eq!(Some(2), vm.syscall_number("log"));
+        assert_eq!(Some("answer"), vm.syscall_name(1));
+        assert_eq!(Ok(ExitStatus::Halted(42)), vm.run());
+        assert_eq!(vec!["svm".to_string()], *log.borrow());
+
+        let mut vm = VM::new(vec![Instruction::SYSCALL { n: 0 }], &[]);
+        vm.register_syscall(0, "last", |host: &mut Host| host.arg(31).map(|_| ()));
+        assert_eq!(FaultReason::RegisterOutOfRange { r: 32 }, vm.run().unwrap_err().reason);
+    }
+
+    #[test]
+    fn run_for() {
+        let mut vm = VM::new(vec![Instruction::INC { r: 0 }, Instruction::JMP { dst: 0 }], &[]);
+
+        assert_eq!(Ok(ExitStatus::Yielded), vm.run_for(5));
+        assert_eq!((3, 1), (vm.ir[0], vm.pc));
+        assert_eq!(Ok(ExitStatus::Yielded), vm.run_for(1));
+        assert_eq!((3, 0), (vm.ir[0], vm.pc));
+        assert!(vm.running);
+
+        vm.set_cost_table(|instruction| match instruction {
+            Instruction::JMP { .. } => 10,
+            _ => 1,
+        });
+        assert_eq!(Ok(ExitStatus::Yielded), vm.run_for(12));
+        assert_eq!((5, 1), (vm.ir[0], vm.pc));
+
+        let mut vm = VM::new(vec![Instruction::LOAD { rd: 0, value: 7 }, Instruction::HLT], &[]);
+        assert_eq!(Ok(ExitStatus::Yielded), vm.run_for(0));
+        assert_eq!(Ok(ExitStatus::Halted(7)), vm.run_for(2));
+    }
+
+    #[test]
+    fn floats() {
+        let program = crate::assembler::Parser::new().process("
+.data
+.code
+loadf $f0 #1.5
+loadf $f1 #-0.25
+load $0 #3
+itof $f2 $0
+fmul $f3 $f0 $f2
+fadd $f3 $f3 $f1
+fdiv $f4 $f0 $f1
+fgt $f3 $f0
+ftoi $0 $f3
+hlt
+").unwrap();
+        let mut vm = VM::new(program.instructions, &program.data);
+
+        assert_eq!(Ok(ExitStatus::Halted(4)), vm.run());
+        assert_eq!([1.5, -0.25, 3.0, 4.25, -6.0], vm.fr[..5]);
+        assert!(vm.compare_flag);
+
+        let mut vm = VM::new(vec![Instruction::FTOI { rd: 0, fs: 32 }], &[]);
+        assert_eq!(FaultReason::FloatRegisterOutOfRange { r: 32 }, vm.run().unwrap_err().reason);
+    }
+
+    #[test]
+    fn bitwise() {
+        let program = crate::assembler::Parser::new().process("
+.data
+.code
+load $1 #-16
+load $2 #4
+and $3 $1 #255
+or $4 $3 $2
+xor $5 $4 #-1
+not $6 $5
+shl $7 $2 #3
+shr $8 $1 $2
+sar $9 $1 #2
+hlt
+").unwrap();
+        let mut vm = VM::new(program.instructions, &program.data);
+        vm.run().unwrap();
+
+        assert_eq!([240, 244, !244, 244, 32, 0x0fff_ffff, -4], vm.ir[3..10]);
+    }
+
+    #[test]
+    fn overflow_modes() {
+        let code = vec![
+            Instruction::LOAD { rd: 1, value: i32::MAX },
+            Instruction::INC { r: 1 },
+            Instruction::HLT,
+        ];
+
+        let mut vm = VM::new(code.clone(), &[]);
+        vm.run().unwrap();
+        assert_eq!((i32::MIN, false, true), (vm.ir[1], vm.carry_flag, vm.overflow_flag));
+
+        let mut vm = VM::new(code.clone(), &[]);
+        vm.overflow_mode = OverflowMode::Saturating;
+        vm.run().unwrap();
+        assert_eq!((i32::MAX, true), (vm.ir[1], vm.overflow_flag));
+
+        let mut vm = VM::new(code, &[]);
+        vm.overflow_mode = OverflowMode::Trapping;
+        assert_eq!(FaultReason::IntegerOverflow, vm.run().unwrap_err().reason);
+        assert_eq!((1, i32::MAX), (vm.pc, vm.ir[1]));
+
+        let mut vm = VM::new(vec![
+            Instruction::LOAD { rd: 1, value: i32::MIN },
+            Instruction::LOAD { rd: 2, value: -1 },
+            Instruction::DIV { rd: 0, rl: 1, rh: 2 },
+        ], &[]);
+        vm.overflow_mode = OverflowMode::Trapping;
+        assert_eq!(FaultReason::IntegerOverflow, vm.run().unwrap_err().reason);
+    }
+
+    #[test]
+    fn multi_word() {
+        // 0x0000_0001_ffff_ffff + 0x0000_0000_0000_0001 in ($2, $1) and ($4, $3)
+        let program = crate::assembler::Parser::new().process("
+.data
+.code
+load $1 #-1
+load $2 #1
+load $3 #1
+load $4 #0
+add $5 $1 $3
+addc $6 $2 $4
+jmpc @carry
+sub $7 $5 $3
+subb $8 $6 $4
+hlt
+carry:
+igl
+").unwrap();
+        let mut vm = VM::new(program.instructions, &program.data);
+        vm.overflow_mode = OverflowMode::Trapping;
+        vm.run().unwrap();
+
+        assert_eq!([0, 2, -1, 1], vm.ir[5..9]);
+        assert!(!vm.carry_flag);
+        assert!(!vm.overflow_flag);
+    }
+
+    #[test]
+    fn data_stack() {
+        // sum(n) = n + sum(n - 1), argument pushed by the caller
+        let program = crate::assembler::Parser::new().process("
+.data
+.code
+load $1 #10
+push $1
+call @sum
+pop $1
+hlt
+sum:
+load $1 [bp]
+load $0 #0
+eq $1 $0
+jmpe @done
+push $1
+load $2 #1
+sub $1 $1 $2
+push $1
+call @sum
+pop $1
+loadw $1 [bp-4]
+add $0 $0 $1
+done:
+ret
+").unwrap();
+        let mut vm = VM::new(program.instructions, &program.data);
+
+        assert_eq!(Ok(ExitStatus::Halted(55)), vm.run());
+        assert_eq!((DEFAULT_MEMORY_SIZE, DEFAULT_MEMORY_SIZE), (vm.sp, vm.bp));
+    }
+
+    #[test]
+    fn stack_limits() {
+        let mut vm = VM::new(vec![Instruction::PUSH { rs: 0 }, Instruction::JMP { dst: 0 }], &[]);
+        vm.stack_size = 8;
+        assert_eq!(FaultReason::StackOverflow, vm.run().unwrap_err().reason);
+        assert_eq!(DEFAULT_MEMORY_SIZE - 8, vm.sp);
+
+        let mut vm = VM::new(vec![Instruction::POP { rd: 0 }], &[]);
+        assert_eq!(FaultReason::StackUnderflow, vm.run().unwrap_err().reason);
+
+        let mut vm = VM::new(vec![Instruction::CALL { dst: 0 }], &[]);
+        vm.max_call_depth = 3;
+        assert_eq!(FaultReason::StackOverflow, vm.run().unwrap_err().reason);
+        assert_eq!(6, vm.stack.len());
+    }
+
+    #[test]
+    fn indirect() {
+        let program = crate::assembler::Parser::new().process("
+.data
+.code
+load $1 &double
+load $2 &square
+load $0 #3
+call $1
+call $2
+load $3 &end
+jmp $3
+igl
+end:
+hlt
+double:
+add $0 $0 $0
+ret
+square:
+mul $0 $0 $0
+ret
+").unwrap();
+        let mut vm = VM::new(program.instructions, &program.data);
+        assert_eq!(Ok(ExitStatus::Halted(36)), vm.run());
+
+        for target in [-1, 2] {
+            let mut vm = VM::new(vec![Instruction::LOAD { rd: 0, value: target }, Instruction::JMPR { r: 0 }], &[]);
+            let fault = vm.run().unwrap_err();
+
+            assert_eq!((1, FaultReason::InvalidJumpTarget { target }), (fault.pc, fault.reason));
+        }
+    }
+
+    #[test]
+    fn switch() {
+        let program = crate::assembler::Parser::new().process("
+.data
+.jumptable ops: @zero, @one, @two
+.code
+switch $1 @ops @other
+zero:
+load $0 #10
+hlt
+one:
+load $0 #11
+hlt
+two:
+load $0 #12
+hlt
+other:
+load $0 #-1
+hlt
+").unwrap();
+
+        for (index, status) in [(0, 10), (1, 11), (2, 12), (3, -1), (-1, -1)] {
+            let mut vm = VM::new(program.instructions.clone(), &program.data);
+            vm.ir[1] = index;
+
+            assert_eq!(Ok(ExitStatus::Halted(status)), vm.run());
+        }
+
+        let mut vm = VM::new(program.instructions.clone(), &program.data);
+        vm.memory.store(4, Width::Word, 99).unwrap();
+        vm.ir[1] = 1;
+        assert_eq!(FaultReason::InvalidJumpTarget { target: 99 }, vm.run().unwrap_err().reason);
+
+        let (table, len) = (usize::MAX - 3, usize::MAX);
+        let mut vm = VM::new(vec![Instruction::SWITCH { r: 1, table, len, default: 0 }], &[]);
+        vm.ir[1] = 1;
+        assert_eq!(FaultReason::MemoryOutOfBounds { addr: table as i64, len: usize::MAX }, vm.run().unwrap_err().reason);
+    }
+
+    #[test]
+    fn nested_loops() {
+        let program = crate::assembler::Parser::new().process("
+.data
+.code
+load $1 #3
+cloop #2
+outer:
+cloop $1
+inner:
+inc $0
+loop @inner
+loop @outer
+hlt
+").unwrap();
+        let mut vm = VM::new(program.instructions, &program.data);
+        assert_eq!(Ok(ExitStatus::Halted(12)), vm.run());
+        assert!(vm.loops.is_empty());
+
+        let mut vm = VM::new(vec![Instruction::CLOOP { count: 1 }, Instruction::JMP { dst: 0 }], &[]);
+        vm.max_loop_depth = 4;
+        assert_eq!(FaultReason::LoopStackOverflow, vm.run().unwrap_err().reason);
+        assert_eq!(vec![1; 4], vm.loops);
+    }
+
+    #[test]
+    fn division() {
+        let program = crate::assembler::Parser::new().process("
+.data
+.code
+load $1 #-7
+load $2 #2
+div $3 $1 $2
+mod $4 $1 $2
+divmod $5 $6 $1 $2
+divu $7 $1 $2
+modu $8 $1 $2
+hlt
+").unwrap();
+        let mut vm = VM::new(program.instructions, &program.data);
+        vm.run().unwrap();
+
+        assert_eq!([-3, -1, -3, -1], vm.ir[3..7]);
+        // -7 is 4294967289 as u32
+        assert_eq!([2147483644, 1], vm.ir[7..9]);
+        assert_eq!(1, vm.remainder);
+    }
+}